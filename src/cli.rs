@@ -3,11 +3,15 @@ use std::path::{Path, PathBuf};
 use clap::Parser;
 use enum_dispatch::enum_dispatch;
 
+mod age;
 mod base64;
 mod csv;
 mod genpass;
+mod hash;
 mod http;
 mod jwt;
+mod key;
+mod secret;
 mod text;
 
 // pub use csv_opts::{CsvOpts, OutputFormat};
@@ -19,7 +23,9 @@ mod text;
 // pub use self::genpass::GenPassOpts;
 // pub use self::http::{HttpServeOpts, HttpSubCommand};
 // pub use self::text::{TextSignFormat, TextSignOpts, TextSubcommand, TextVerifyOpts};
-pub use self::{base64::*, csv::*, genpass::*, http::*, jwt::*, text::*};
+pub use self::{
+    age::*, base64::*, csv::*, genpass::*, hash::*, http::*, jwt::*, key::*, secret::*, text::*,
+};
 
 #[derive(Debug, Parser)]
 #[command(name = "rcli", version, author, about, long_about = None)]
@@ -35,6 +41,11 @@ pub enum SubCommand {
     Csv(CsvOpts),
     #[command(name = "genpass", about = "Generate a random password")]
     GenPass(GenPassOpts),
+    #[command(
+        name = "hash",
+        about = "Hash files or stdin, or check a sha256sum style manifest"
+    )]
+    Hash(HashOpts),
     #[command(subcommand, about = "Base64 encode/decode")]
     Base64(Base64Subcommand),
     #[command(subcommand, about = "Text sign/verify")]
@@ -43,6 +54,12 @@ pub enum SubCommand {
     Http(HttpSubCommand),
     #[command(subcommand, about = "JWT sign/verify")]
     Jwt(JwtSubCommand),
+    #[command(subcommand, about = "Manage named keys in the keyring")]
+    Key(KeySubCommand),
+    #[command(subcommand, about = "age file encryption, compatible with age/rage")]
+    Age(AgeSubCommand),
+    #[command(subcommand, about = "Split a key into shares and combine them again")]
+    Secret(SecretSubCommand),
 }
 
 // 会传入文件名
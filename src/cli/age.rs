@@ -0,0 +1,156 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+};
+
+use clap::Parser;
+use enum_dispatch::enum_dispatch;
+
+use super::verify_file;
+use crate::{
+    get_content, get_passphrase, get_reader, process_age_decrypt, process_age_encrypt,
+    process_age_encrypt_passphrase, process_age_keygen, write_file_atomic, write_key_file,
+    CmdExecutor, ProcessError,
+};
+
+// region:    --- enum and struct
+#[derive(Debug, Parser)]
+#[enum_dispatch(CmdExecutor)]
+pub enum AgeSubCommand {
+    #[command(about = "Encrypt a file to age recipients or with a passphrase")]
+    Encrypt(AgeEncryptOpts),
+    #[command(about = "Decrypt an age file with identity files or a passphrase")]
+    Decrypt(AgeDecryptOpts),
+    #[command(about = "Generate an age X25519 identity")]
+    Keygen(AgeKeygenOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct AgeEncryptOpts {
+    #[arg(value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// Recipient public key (age1...), can be repeated
+    #[arg(short, long = "recipient", required_unless_present_any = ["recipients_file", "passphrase"])]
+    pub recipients: Vec<String>,
+    /// File with one recipient per line, `#` comments are ignored. Can be repeated
+    #[arg(short = 'R', long, value_parser = verify_file)]
+    pub recipients_file: Vec<String>,
+    /// Encrypt with a passphrase (scrypt) instead of recipients
+    #[arg(short, long, conflicts_with_all = ["recipients", "recipients_file"])]
+    pub passphrase: bool,
+    #[arg(long, value_parser = verify_file, requires = "passphrase")]
+    pub passphrase_file: Option<String>,
+    /// PEM encoded ASCII armor output
+    #[arg(short, long)]
+    pub armor: bool,
+    /// Output file, defaults to stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Parser)]
+pub struct AgeDecryptOpts {
+    #[arg(value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// Identity file (AGE-SECRET-KEY-1...), can be repeated.
+    /// Passphrase encrypted files don't need one
+    #[arg(short, long = "identity", value_parser = verify_file)]
+    pub identities: Vec<String>,
+    #[arg(long, value_parser = verify_file)]
+    pub passphrase_file: Option<String>,
+    /// Output file, only written when the whole file decrypts successfully. Defaults to stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Parser)]
+pub struct AgeKeygenOpts {
+    /// Identity file, defaults to stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+// endregion: --- enum and struct
+
+// region:    --- impls
+impl CmdExecutor for AgeEncryptOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let mut reader = get_reader(&self.input)?;
+        let passphrase = if self.passphrase {
+            Some(get_passphrase(self.passphrase_file.as_deref(), true)?)
+        } else {
+            None
+        };
+        let mut recipients = self.recipients;
+        for file in &self.recipients_file {
+            let content = String::from_utf8(get_content(file)?)?;
+            recipients.extend(
+                content
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(String::from),
+            );
+        }
+        let mut encrypt = |writer: &mut dyn Write| match &passphrase {
+            Some(passphrase) => {
+                process_age_encrypt_passphrase(&mut reader, writer, passphrase, self.armor)
+            }
+            None => process_age_encrypt(&mut reader, writer, &recipients, self.armor),
+        };
+        match self.output {
+            Some(output) => {
+                let mut writer = BufWriter::new(File::create(output)?);
+                encrypt(&mut writer)?;
+                writer.flush()?;
+            }
+            None => encrypt(&mut io::stdout().lock())?,
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for AgeDecryptOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let mut reader = get_reader(&self.input)?;
+        let identities = self
+            .identities
+            .iter()
+            .map(|path| get_content(path))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let passphrase =
+            || get_passphrase(self.passphrase_file.as_deref(), false).map_err(ProcessError::other);
+        match &self.output {
+            Some(output) => write_file_atomic(output, |writer| {
+                Ok(process_age_decrypt(
+                    &mut reader,
+                    writer,
+                    &identities,
+                    passphrase,
+                )?)
+            })?,
+            None => process_age_decrypt(
+                &mut reader,
+                &mut io::stdout().lock(),
+                &identities,
+                passphrase,
+            )?,
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for AgeKeygenOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let (identity, recipient) = process_age_keygen();
+        match self.output {
+            Some(output) => {
+                write_key_file(&output, identity.as_bytes())?;
+                eprintln!("Public key: {}", recipient);
+            }
+            None => print!("{}", identity),
+        }
+        Ok(())
+    }
+}
+// endregion: --- impls
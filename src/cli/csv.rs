@@ -48,7 +48,8 @@ impl CmdExecutor for CsvOpts {
         } else {
             format!("output.{}", self.format)
         };
-        process_csv(&self.input, &output, &self.format)
+        process_csv(&self.input, &output, &self.format)?;
+        Ok(())
     }
 }
 
@@ -0,0 +1,172 @@
+use std::{
+    fmt, fs,
+    io::{self, Write},
+    path::PathBuf,
+    str::FromStr,
+};
+
+use clap::Parser;
+
+use super::verify_file;
+use crate::{
+    collect_files, format_manifest_line, get_content, get_reader, process_hash, process_hash_check,
+    process_hash_files, CmdExecutor, HashCheckStatus,
+};
+
+// rcli hash Cargo.toml src -r > SHA256SUMS
+// rcli hash --check SHA256SUMS
+#[derive(Debug, Parser)]
+pub struct HashOpts {
+    /// Files or directories to hash, `-` for stdin
+    #[arg(default_value = "-")]
+    pub inputs: Vec<String>,
+
+    /// sha256 when hashing, --check infers it from the hash length when not given
+    #[arg(short, long, value_parser = parse_hash_algorithm)]
+    pub algorithm: Option<HashAlgorithm>,
+
+    /// Hash every file under the given directories
+    #[arg(short, long)]
+    pub recursive: bool,
+
+    /// Write the manifest to a file instead of stdout
+    #[arg(short, long, conflicts_with = "check")]
+    pub output: Option<PathBuf>,
+
+    /// Verify the files listed in a `sha256sum` style manifest
+    #[arg(short, long, value_parser = verify_file)]
+    pub check: Option<String>,
+
+    /// Only report mismatched and missing files in --check mode
+    #[arg(short, long, requires = "check")]
+    pub quiet: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Blake3,
+    Sha256,
+    Sha512,
+    Sha3_256,
+    Sha3_512,
+    Md5,
+    Crc32,
+}
+
+// region:    --- impls
+impl CmdExecutor for HashOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        if let Some(manifest) = &self.check {
+            return check_manifest(manifest, self.algorithm, self.quiet);
+        }
+        let algorithm = self.algorithm.unwrap_or(HashAlgorithm::Sha256);
+
+        let mut lines = Vec::new();
+        let (stdin, inputs): (Vec<_>, Vec<_>) = self.inputs.into_iter().partition(|i| i == "-");
+        if !stdin.is_empty() {
+            let hash = process_hash(&mut get_reader("-")?, algorithm)?;
+            lines.push(format!("{}  -", hash));
+        }
+        let files = collect_files(&inputs, self.recursive)?;
+        for (path, hash) in process_hash_files(&files, algorithm) {
+            let hash = hash.map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+            lines.push(format_manifest_line(&hash, &path));
+        }
+
+        match self.output {
+            Some(output) => fs::write(output, lines.join("\n") + "\n")?,
+            None => {
+                let mut stdout = io::stdout().lock();
+                for line in lines {
+                    writeln!(stdout, "{}", line)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn check_manifest(
+    manifest: &str,
+    algorithm: Option<HashAlgorithm>,
+    quiet: bool,
+) -> anyhow::Result<()> {
+    let content = String::from_utf8(get_content(manifest)?)?;
+    let report = process_hash_check(&content, algorithm)?;
+    let mut mismatched = 0;
+    let mut missing = 0;
+    let mut unreadable = 0;
+    for entry in &report {
+        // 输出格式和 sha256sum -c 保持一致
+        match &entry.status {
+            HashCheckStatus::Ok if !quiet => println!("{}: OK", entry.path),
+            HashCheckStatus::Ok => {}
+            HashCheckStatus::Mismatch => {
+                mismatched += 1;
+                println!("{}: FAILED", entry.path);
+            }
+            HashCheckStatus::Missing => {
+                missing += 1;
+                println!("{}: FAILED open or read", entry.path);
+                eprintln!("{}: No such file or directory", entry.path);
+            }
+            HashCheckStatus::Unreadable(e) => {
+                unreadable += 1;
+                println!("{}: FAILED open or read", entry.path);
+                eprintln!("{}: {}", entry.path, e);
+            }
+        }
+    }
+    if mismatched + missing + unreadable > 0 {
+        anyhow::bail!(
+            "{} of {} files did not match, {} are missing, {} could not be read",
+            mismatched,
+            report.len(),
+            missing,
+            unreadable
+        );
+    }
+    Ok(())
+}
+
+impl From<HashAlgorithm> for &'static str {
+    fn from(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Blake3 => "blake3",
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Sha512 => "sha512",
+            HashAlgorithm::Sha3_256 => "sha3-256",
+            HashAlgorithm::Sha3_512 => "sha3-512",
+            HashAlgorithm::Md5 => "md5",
+            HashAlgorithm::Crc32 => "crc32",
+        }
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(algorithm: &str) -> Result<Self, Self::Err> {
+        match algorithm {
+            "blake3" => Ok(HashAlgorithm::Blake3),
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            "sha512" => Ok(HashAlgorithm::Sha512),
+            "sha3-256" => Ok(HashAlgorithm::Sha3_256),
+            "sha3-512" => Ok(HashAlgorithm::Sha3_512),
+            "md5" => Ok(HashAlgorithm::Md5),
+            "crc32" => Ok(HashAlgorithm::Crc32),
+            _ => Err(anyhow::anyhow!("Invalid hash algorithm: {}", algorithm)),
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+// endregion: --- impls
+
+fn parse_hash_algorithm(algorithm: &str) -> Result<HashAlgorithm, anyhow::Error> {
+    algorithm.parse()
+}
@@ -1,31 +1,438 @@
-use std::path::PathBuf;
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
 
 use clap::Parser;
 use enum_dispatch::enum_dispatch;
 
-use crate::{process_http_serve, CmdExecutor};
+use crate::{
+    load_htpasswd, load_mock_spec, load_serve_rules, process_http_mock, process_http_serve,
+    CmdExecutor, HttpAuth, HttpCors, HttpJwtAuth, HttpMockConfig, HttpPassword, HttpProxy,
+    HttpServeConfig, HttpTls, HttpUpload, HttpUser, ServeRules,
+};
 
-use super::verify_path;
+use super::{jwt::jwt_key, verify_file, verify_path};
 
+// HS256 的 key 不应短于哈希输出
+const MIN_JWT_KEY_LEN: usize = 32;
+
+// 只在启动时解析一次, 不必装箱
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Parser)]
 #[enum_dispatch(CmdExecutor)]
 pub enum HttpSubCommand {
     #[command(about = "Serve a directory over HTTP")]
     Serve(HttpServeOpts),
+    #[command(about = "Forward requests to an upstream server, optionally next to static files")]
+    Proxy(HttpProxyOpts),
+    #[command(about = "Serve canned API responses from a YAML/JSON spec")]
+    Mock(HttpMockOpts),
 }
 
 #[derive(Debug, Parser)]
 pub struct HttpServeOpts {
     #[arg(short, long, value_parser = verify_path, default_value = ".")]
     pub dir: PathBuf,
+    /// Address to listen on, `0.0.0.0` or `::` for all interfaces
+    #[arg(short, long, default_value_t = IpAddr::V4(Ipv4Addr::LOCALHOST))]
+    pub bind: IpAddr,
+    /// 0 picks a free port
+    #[arg(short, long, default_value_t = 8080)]
+    pub port: u16,
+    /// PEM certificate (chain) for HTTPS
+    #[arg(long, value_parser = verify_file, requires = "tls_key")]
+    pub tls_cert: Option<String>,
+    /// PEM private key for HTTPS
+    #[arg(long, value_parser = verify_file, requires = "tls_cert")]
+    pub tls_key: Option<String>,
+    /// Serve HTTPS with an ephemeral certificate for localhost
+    #[arg(long, conflicts_with = "tls_cert")]
+    pub self_signed: bool,
+    /// Seconds to wait for open requests on Ctrl-C/SIGTERM
+    #[arg(long, default_value_t = 10)]
+    pub shutdown_timeout: u64,
+    /// Serve symlinks that point inside the directory, by default all symlinks are refused
+    #[arg(long)]
+    pub follow_symlinks: bool,
+    /// Tera template for directory listings instead of the built-in one
+    #[arg(long, value_parser = verify_file)]
+    pub template: Option<String>,
+    /// Accept uploads from the listing page (multipart) and `PUT` requests
+    #[arg(long)]
+    pub allow_upload: bool,
+    /// Largest accepted file, bytes or with a K/M/G suffix
+    #[arg(long, default_value = "100M", value_parser = parse_size, requires = "allow_upload")]
+    pub max_upload_size: u64,
+    /// What to do when an uploaded file already exists: deny, replace, rename
+    #[arg(long, default_value = "deny", value_parser = parse_overwrite_policy, requires = "allow_upload")]
+    pub overwrite: OverwritePolicy,
+    /// Require HTTP Basic auth, `user:pass`, can be repeated
+    #[arg(long, value_parser = parse_user)]
+    pub auth: Vec<HttpUser>,
+    /// htpasswd file with bcrypt (`htpasswd -B`) or argon2 hashes
+    #[arg(long, value_parser = verify_file)]
+    pub auth_file: Option<String>,
+    /// Accept `Authorization: Bearer` tokens from `jwt sign` signed with this secret
+    #[arg(long, group = "jwt")]
+    pub jwt_key: Option<String>,
+    /// Same as `--jwt-key`, with the secret read from a file
+    #[arg(long, value_parser = verify_file, group = "jwt")]
+    pub jwt_key_file: Option<String>,
+    /// Same as `--jwt-key`, with a symmetric keyring key by name or fingerprint
+    #[arg(long, group = "jwt")]
+    pub jwt_key_name: Option<String>,
+    /// Audience bearer tokens must have
+    #[arg(long, requires = "jwt")]
+    pub jwt_aud: Option<String>,
+    /// Subject bearer tokens must have
+    #[arg(long, requires = "jwt")]
+    pub jwt_sub: Option<String>,
+    /// Don't compress responses with gzip/br/zstd
+    #[arg(long)]
+    pub no_compress: bool,
+    /// Send CORS headers for this origin, `*` for any, can be repeated
+    #[arg(long)]
+    pub cors_origin: Vec<String>,
+    /// Allow cookies and auth headers in CORS requests, needs explicit origins
+    #[arg(long, requires = "cors_origin")]
+    pub cors_credentials: bool,
+    /// Seconds browsers may cache a CORS preflight response
+    #[arg(long, requires = "cors_origin")]
+    pub cors_max_age: Option<u64>,
+    /// Access log format: common, json, off
+    #[arg(long, default_value = "common", value_parser = parse_access_log_format)]
+    pub access_log: AccessLogFormat,
+    /// Show markdown, CSV and source files as HTML pages in browsers, `?raw` for the file itself
+    #[arg(long)]
+    pub render: bool,
+    /// Reload open pages when files change, stylesheets are swapped without a reload
+    #[arg(long)]
+    pub watch: bool,
+    /// Serve `index.html` for unknown paths, for single-page apps with client-side routing
+    #[arg(long)]
+    pub spa: bool,
+    /// serve.toml with redirects, rewrites, headers, 404 pages and extra mounted directories
+    #[arg(long, value_parser = verify_file)]
+    pub config: Option<String>,
+}
+
+#[derive(Debug, Parser)]
+pub struct HttpProxyOpts {
+    /// Server to forward to, e.g. http://127.0.0.1:3000
+    #[arg(short, long)]
+    pub upstream: String,
+    /// Url prefix to forward, e.g. /api
+    #[arg(long, default_value = "/")]
+    pub mount: String,
+    /// Drop the mount prefix from forwarded paths
+    #[arg(long)]
+    pub strip_prefix: bool,
+    /// Also serve this directory at /, needs a --mount other than /
+    #[arg(short, long, value_parser = verify_path)]
+    pub dir: Option<PathBuf>,
+    /// Address to listen on
+    #[arg(short, long, default_value_t = IpAddr::V4(Ipv4Addr::LOCALHOST))]
+    pub bind: IpAddr,
+    /// 0 picks a free port
+    #[arg(short, long, default_value_t = 8080)]
+    pub port: u16,
+    /// Access log format: common, json, off
+    #[arg(long, default_value = "common", value_parser = parse_access_log_format)]
+    pub access_log: AccessLogFormat,
+}
+
+#[derive(Debug, Parser)]
+pub struct HttpMockOpts {
+    /// Routes with canned responses, YAML or JSON
+    #[arg(short, long, value_parser = verify_file)]
+    pub spec: String,
+    /// Address to listen on
+    #[arg(short, long, default_value_t = IpAddr::V4(Ipv4Addr::LOCALHOST))]
+    pub bind: IpAddr,
+    /// 0 picks a free port
     #[arg(short, long, default_value_t = 8080)]
     pub port: u16,
+    /// Send CORS headers for this origin, `*` for any, can be repeated
+    #[arg(long)]
+    pub cors_origin: Vec<String>,
+    /// Access log format: common, json, off
+    #[arg(long, default_value = "common", value_parser = parse_access_log_format)]
+    pub access_log: AccessLogFormat,
+}
+
+/// how `http serve` writes access logs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessLogFormat {
+    /// Common Log Format plus latency
+    Common,
+    /// one JSON object per request
+    Json,
+    Off,
+}
+
+/// what an upload does when the target file already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    /// 409 Conflict
+    Deny,
+    Replace,
+    /// store as `name (1).ext`
+    Rename,
 }
 
 // region:    --- impls
 impl CmdExecutor for HttpServeOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        process_http_serve(self.dir, self.port).await
+        let tls = match (self.tls_cert, self.tls_key) {
+            (Some(cert), Some(key)) => Some(HttpTls::Pem {
+                cert: cert.into(),
+                key: key.into(),
+            }),
+            _ if self.self_signed => Some(HttpTls::SelfSigned),
+            _ => None,
+        };
+        let mut users = self.auth;
+        if let Some(auth_file) = &self.auth_file {
+            users.extend(load_htpasswd(auth_file.as_ref())?);
+        }
+        let jwt = match (&self.jwt_key, &self.jwt_key_file, &self.jwt_key_name) {
+            (None, None, None) => None,
+            (key, file, name) => {
+                if let Some(key) = key {
+                    verify_jwt_secret(key)?;
+                }
+                let key = jwt_key(
+                    key.as_deref().unwrap_or_default(),
+                    file.as_deref(),
+                    name.as_deref(),
+                )?;
+                if key.len() < MIN_JWT_KEY_LEN {
+                    anyhow::bail!(
+                        "JWT key must be at least {} bytes, got {}",
+                        MIN_JWT_KEY_LEN,
+                        key.len()
+                    );
+                }
+                Some(HttpJwtAuth {
+                    key,
+                    aud: self.jwt_aud,
+                    sub: self.jwt_sub,
+                })
+            }
+        };
+        let auth = (!users.is_empty() || jwt.is_some()).then_some(HttpAuth { users, jwt });
+        let cors = (!self.cors_origin.is_empty()).then_some(HttpCors {
+            origins: self.cors_origin,
+            allow_credentials: self.cors_credentials,
+            max_age: self.cors_max_age.map(Duration::from_secs),
+        });
+        let rules = match &self.config {
+            Some(path) => load_serve_rules(path.as_ref())?,
+            None => ServeRules::default(),
+        };
+        let config = HttpServeConfig {
+            path: self.dir,
+            serve_dir: true,
+            addr: SocketAddr::new(self.bind, self.port),
+            tls,
+            shutdown_timeout: Duration::from_secs(self.shutdown_timeout),
+            follow_symlinks: self.follow_symlinks,
+            listing_template: self.template.map(PathBuf::from),
+            upload: self.allow_upload.then_some(HttpUpload {
+                max_size: self.max_upload_size,
+                overwrite: self.overwrite,
+            }),
+            auth,
+            compression: !self.no_compress,
+            cors,
+            access_log: self.access_log,
+            render: self.render,
+            watch: self.watch,
+            spa: self.spa,
+            rules,
+            proxies: Vec::new(),
+        };
+        process_http_serve(config).await?;
+        Ok(())
+    }
+}
+
+impl CmdExecutor for HttpProxyOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        if self.dir.is_some() && self.mount.trim_end_matches('/').is_empty() {
+            anyhow::bail!("--dir needs a --mount other than /");
+        }
+        let config = HttpServeConfig {
+            serve_dir: self.dir.is_some(),
+            path: self.dir.unwrap_or_else(|| PathBuf::from(".")),
+            addr: SocketAddr::new(self.bind, self.port),
+            access_log: self.access_log,
+            proxies: vec![HttpProxy {
+                mount: self.mount,
+                upstream: self.upstream,
+                strip_prefix: self.strip_prefix,
+            }],
+            ..Default::default()
+        };
+        process_http_serve(config).await?;
+        Ok(())
+    }
+}
+
+impl CmdExecutor for HttpMockOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let config = HttpMockConfig {
+            spec: load_mock_spec(self.spec.as_ref())?,
+            addr: SocketAddr::new(self.bind, self.port),
+            shutdown_timeout: Duration::from_secs(10),
+            cors: (!self.cors_origin.is_empty()).then_some(HttpCors {
+                origins: self.cors_origin,
+                allow_credentials: false,
+                max_age: None,
+            }),
+            access_log: self.access_log,
+        };
+        process_http_mock(config).await?;
+        Ok(())
+    }
+}
+
+impl FromStr for AccessLogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "common" => Ok(AccessLogFormat::Common),
+            "json" => Ok(AccessLogFormat::Json),
+            "off" => Ok(AccessLogFormat::Off),
+            _ => Err(anyhow::anyhow!("Invalid access log format")),
+        }
+    }
+}
+
+impl From<AccessLogFormat> for &'static str {
+    fn from(format: AccessLogFormat) -> Self {
+        match format {
+            AccessLogFormat::Common => "common",
+            AccessLogFormat::Json => "json",
+            AccessLogFormat::Off => "off",
+        }
+    }
+}
+
+impl fmt::Display for AccessLogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl FromStr for OverwritePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "deny" => Ok(OverwritePolicy::Deny),
+            "replace" => Ok(OverwritePolicy::Replace),
+            "rename" => Ok(OverwritePolicy::Rename),
+            _ => Err(anyhow::anyhow!("Invalid overwrite policy")),
+        }
+    }
+}
+
+impl From<OverwritePolicy> for &'static str {
+    fn from(policy: OverwritePolicy) -> Self {
+        match policy {
+            OverwritePolicy::Deny => "deny",
+            OverwritePolicy::Replace => "replace",
+            OverwritePolicy::Rename => "rename",
+        }
+    }
+}
+
+impl fmt::Display for OverwritePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
     }
 }
 // endregion: --- impls
+
+fn parse_user(user: &str) -> Result<HttpUser, anyhow::Error> {
+    match user.split_once(':') {
+        Some((name, password)) if !name.is_empty() => Ok(HttpUser {
+            name: name.to_string(),
+            password: HttpPassword::Plain(password.to_string()),
+        }),
+        _ => Err(anyhow::anyhow!("expected user:pass")),
+    }
+}
+
+// `jwt sign` 默认的空 key 和短密码都能被轻易伪造
+fn verify_jwt_secret(key: &str) -> anyhow::Result<()> {
+    // 本该用 --jwt-key-file 的文件, 打错的路径由长度检查拦住
+    if Path::new(key).exists() || key.starts_with("~/") {
+        anyhow::bail!(
+            "--jwt-key {:?} looks like a file path, use --jwt-key-file for key files",
+            key
+        );
+    }
+    Ok(())
+}
+
+fn parse_access_log_format(format: &str) -> Result<AccessLogFormat, anyhow::Error> {
+    format.parse()
+}
+
+fn parse_overwrite_policy(policy: &str) -> Result<OverwritePolicy, anyhow::Error> {
+    policy.parse()
+}
+
+// 1024 进制: 10K, 100M, 2G
+fn parse_size(size: &str) -> Result<u64, anyhow::Error> {
+    let size = size.trim();
+    let (number, unit) = match size.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => size.split_at(i),
+        None => (size, ""),
+    };
+    let shift = match unit.to_ascii_uppercase().as_str() {
+        "" | "B" => 0,
+        "K" | "KB" | "KIB" => 10,
+        "M" | "MB" | "MIB" => 20,
+        "G" | "GB" | "GIB" => 30,
+        _ => return Err(anyhow::anyhow!("Invalid size unit: {}", unit)),
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid size: {}", size))?;
+    number
+        .checked_mul(1 << shift)
+        .ok_or_else(|| anyhow::anyhow!("Size too large: {}", size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("1024").unwrap(), 1024);
+        assert_eq!(parse_size("10K").unwrap(), 10 * 1024);
+        assert_eq!(parse_size("100M").unwrap(), 100 * 1024 * 1024);
+        assert_eq!(parse_size("2gb").unwrap(), 2 * 1024 * 1024 * 1024);
+        assert!(parse_size("10X").is_err());
+        assert!(parse_size("M").is_err());
+    }
+
+    #[test]
+    fn test_verify_jwt_secret() {
+        assert!(verify_jwt_secret("0123456789abcdef0123456789abcdef").is_ok());
+        assert!(verify_jwt_secret("q9/Zk3+Vb2WmLs8pXo1tRy4uNe7cHa0J").is_ok());
+        assert!(verify_jwt_secret("fixtures/blake3.txt").is_err());
+        // 以存在的目录开头的 secret 也是合法的
+        assert!(verify_jwt_secret("src/Zk3+Vb2WmLs8pXo1tRy4uNe7cHa0J").is_ok());
+        assert!(verify_jwt_secret("~/.config/jwt.key").is_err());
+    }
+}
@@ -1,4 +1,7 @@
-use crate::{process_jwt_sign, process_jwt_verify, CmdExecutor};
+use super::verify_file;
+use crate::{
+    get_content, is_sealed_key, process_jwt_sign, process_jwt_verify, CmdExecutor, KeyKind, Keyring,
+};
 use anyhow::Result;
 use clap::Parser;
 use enum_dispatch::enum_dispatch;
@@ -15,9 +18,15 @@ pub enum JwtSubCommand {
 
 #[derive(Debug, Clone, Parser)]
 pub struct JwtSignOpts {
-    /// key to sign the token
-    #[arg(short, long, default_value = "")]
+    /// HS256 secret
+    #[arg(short, long, default_value = "", conflicts_with_all = ["key_file", "key_name"])]
     key: String,
+    /// read the secret from a file, `-` for stdin
+    #[arg(long, value_parser = verify_file, conflicts_with = "key_name")]
+    key_file: Option<String>,
+    /// name or fingerprint of a symmetric keyring key
+    #[arg(long)]
+    key_name: Option<String>,
     #[arg(short, long)]
     /// subject
     sub: String,
@@ -31,8 +40,15 @@ pub struct JwtSignOpts {
 
 #[derive(Debug, Clone, Parser)]
 pub struct JwtVerifyOpts {
-    #[arg(short, long, default_value = "")]
+    /// same as `jwt sign --key`
+    #[arg(short, long, default_value = "", conflicts_with_all = ["key_file", "key_name"])]
     key: String,
+    /// same as `jwt sign --key-file`
+    #[arg(long, value_parser = verify_file, conflicts_with = "key_name")]
+    key_file: Option<String>,
+    /// same as `jwt sign --key-name`
+    #[arg(long)]
+    key_name: Option<String>,
     #[arg(short, long)]
     token: String,
 }
@@ -41,7 +57,12 @@ pub struct JwtVerifyOpts {
 // region:    --- impls
 impl CmdExecutor for JwtSignOpts {
     async fn execute(self) -> Result<()> {
-        let token = process_jwt_sign(&self.key, &self.sub, &self.aud, self.exp)?;
+        let key = jwt_key(
+            &self.key,
+            self.key_file.as_deref(),
+            self.key_name.as_deref(),
+        )?;
+        let token = process_jwt_sign(&key, &self.sub, &self.aud, self.exp)?;
         println!("Token: {}", token);
         Ok(())
     }
@@ -49,13 +70,48 @@ impl CmdExecutor for JwtSignOpts {
 
 impl CmdExecutor for JwtVerifyOpts {
     async fn execute(self) -> Result<()> {
-        let verified: bool = process_jwt_verify(&self.key, &self.token)?;
+        let key = jwt_key(
+            &self.key,
+            self.key_file.as_deref(),
+            self.key_name.as_deref(),
+        )?;
+        let verified: bool = process_jwt_verify(&key, &self.token)?;
         println!("Token valid: {}", verified);
         Ok(())
     }
 }
 // endregion: --- impls
 
+/// the HS256 secret from exactly one source: a key file, a keyring key or the secret itself
+pub(crate) fn jwt_key(secret: &str, file: Option<&str>, name: Option<&str>) -> Result<Vec<u8>> {
+    match (file, name) {
+        (Some(file), _) => get_content(file),
+        (None, Some(name)) => keyring_secret(name),
+        (None, None) => Ok(secret.as_bytes().to_vec()),
+    }
+}
+
+// HS256 是对称的, 只能用 keyring 中的对称 key, ed25519 等的私钥不能当 secret
+fn keyring_secret(name: &str) -> Result<Vec<u8>> {
+    let entry = Keyring::open_default()?.get(name)?;
+    let format = entry.format()?;
+    if !format.is_symmetric() {
+        anyhow::bail!(
+            "{} is an asymmetric {} key, JWTs need a symmetric keyring key",
+            entry.name,
+            format
+        );
+    }
+    let key = entry.key(KeyKind::Private)?;
+    if is_sealed_key(&key) {
+        anyhow::bail!(
+            "{} is encrypted, export it to a --key-file first",
+            entry.name
+        );
+    }
+    Ok(key)
+}
+
 fn parse_exp(exp: &str) -> Result<usize> {
     match fancy_duration::FancyDuration::<std::time::Duration>::parse(exp) {
         Ok(d) => Ok(d.0.as_secs() as usize),
@@ -74,4 +130,13 @@ mod tests {
         assert_eq!(parse_exp("1h").unwrap(), 60 * 60);
         assert_eq!(parse_exp("1d").unwrap(), 60 * 60 * 24);
     }
+
+    #[test]
+    fn test_jwt_key_sources() -> Result<()> {
+        let file = "fixtures/blake3.txt";
+        // 字面量即使是一个存在的文件名也不读取文件
+        assert_eq!(jwt_key(file, None, None)?, file.as_bytes());
+        assert_eq!(jwt_key("", Some(file), None)?, std::fs::read(file)?);
+        Ok(())
+    }
 }
@@ -0,0 +1,135 @@
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use clap::Parser;
+use enum_dispatch::enum_dispatch;
+
+use super::{text::parse_text_sign_format, verify_file};
+use crate::{
+    get_content, get_passphrase, process_key_import, write_key_file, CmdExecutor, KeyKind, Keyring,
+    ProcessError, TextSignFormat,
+};
+
+// region:    --- enum and struct
+#[derive(Debug, Parser)]
+#[enum_dispatch(CmdExecutor)]
+pub enum KeySubCommand {
+    #[command(about = "List keys in the keyring (~/.config/rcli/keyring)")]
+    List(KeyListOpts),
+    #[command(about = "Import key files into the keyring")]
+    Import(KeyImportOpts),
+    #[command(about = "Export a key from the keyring")]
+    Export(KeyExportOpts),
+    #[command(about = "Delete a key from the keyring")]
+    Delete(KeyDeleteOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct KeyListOpts {}
+
+#[derive(Debug, Parser)]
+pub struct KeyImportOpts {
+    /// Name used by `--key`
+    pub name: String,
+    #[arg(long, value_parser = parse_text_sign_format)]
+    pub format: TextSignFormat,
+    /// Private key file, may be encrypted with `text generate --encrypt`
+    #[arg(long, value_parser = verify_file, required_unless_present = "public")]
+    pub private: Option<String>,
+    /// Public key file
+    #[arg(long, value_parser = verify_file)]
+    pub public: Option<String>,
+    /// Replace an existing key with the same name
+    #[arg(long)]
+    pub force: bool,
+    #[arg(long, value_parser = verify_file)]
+    pub passphrase_file: Option<String>,
+}
+
+#[derive(Debug, Parser)]
+pub struct KeyExportOpts {
+    /// Key name or fingerprint
+    pub key: String,
+    /// Export the private key instead of the public key
+    #[arg(long)]
+    pub private: bool,
+    /// Output file, defaults to stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Parser)]
+pub struct KeyDeleteOpts {
+    /// Key name or fingerprint
+    pub key: String,
+}
+// endregion: --- enum and struct
+
+// region:    --- impls
+impl CmdExecutor for KeyListOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let keyring = Keyring::open_default()?;
+        let entries = keyring.list()?;
+        if entries.is_empty() {
+            eprintln!("No keys in {}", keyring.dir().display());
+            return Ok(());
+        }
+        println!(
+            "{:<20} {:<12} {:<18} KEYS",
+            "NAME", "ALGORITHM", "FINGERPRINT"
+        );
+        for entry in entries {
+            let keys = match (&entry.private, &entry.public) {
+                (Some(_), Some(_)) => "private, public",
+                (Some(_), None) => "private",
+                _ => "public",
+            };
+            println!(
+                "{:<20} {:<12} {:<18} {}",
+                entry.name, entry.algorithm, entry.fingerprint, keys
+            );
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for KeyImportOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let private = self.private.as_deref().map(get_content).transpose()?;
+        let public = self.public.as_deref().map(get_content).transpose()?;
+        let passphrase =
+            || get_passphrase(self.passphrase_file.as_deref(), false).map_err(ProcessError::other);
+        let entry = process_key_import(&self.name, self.format, private, public, passphrase)?;
+        Keyring::open_default()?.add(&entry, self.force)?;
+        println!("Imported {} ({})", entry.name, entry.fingerprint);
+        Ok(())
+    }
+}
+
+impl CmdExecutor for KeyExportOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let entry = Keyring::open_default()?.get(&self.key)?;
+        let kind = if self.private {
+            KeyKind::Private
+        } else {
+            KeyKind::Public
+        };
+        let key = entry.key(kind)?;
+        match self.output {
+            Some(output) => write_key_file(output, &key)?,
+            None => io::stdout().write_all(&key)?,
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for KeyDeleteOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let entry = Keyring::open_default()?.delete(&self.key)?;
+        println!("Deleted {} ({})", entry.name, entry.fingerprint);
+        Ok(())
+    }
+}
+// endregion: --- impls
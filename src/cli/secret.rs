@@ -0,0 +1,96 @@
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use clap::Parser;
+use enum_dispatch::enum_dispatch;
+
+use super::{text::parse_key_encoding, verify_file, verify_path};
+use crate::{
+    get_content, process_secret_combine, process_secret_split, write_key_file, CmdExecutor,
+    KeyEncoding, SecretShare,
+};
+
+// region:    --- enum and struct
+#[derive(Debug, Parser)]
+#[enum_dispatch(CmdExecutor)]
+pub enum SecretSubCommand {
+    #[command(about = "Split a key file into shares (Shamir's secret sharing)")]
+    Split(SecretSplitOpts),
+    #[command(about = "Rebuild a key file from enough shares")]
+    Combine(SecretCombineOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct SecretSplitOpts {
+    /// Key file to split, e.g. the output of `text generate`
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// Number of shares needed to rebuild the key
+    #[arg(short, long)]
+    pub threshold: u8,
+    /// Number of shares to create
+    #[arg(short = 'n', long)]
+    pub shares: u8,
+    #[arg(long, default_value = "base64", value_parser = parse_key_encoding)]
+    pub encoding: KeyEncoding,
+    /// Write one `share-<n>-of-<shares>.txt` file per share instead of printing them
+    #[arg(short, long, value_parser = verify_path)]
+    pub output_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Parser)]
+pub struct SecretCombineOpts {
+    /// Share files, one or more shares per file, `-` for stdin
+    #[arg(required = true, value_parser = verify_file)]
+    pub shares: Vec<String>,
+    /// Output file, defaults to stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+// endregion: --- enum and struct
+
+// region:    --- impls
+impl CmdExecutor for SecretSplitOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let secret = get_content(&self.input)?;
+        let shares = process_secret_split(&secret, self.threshold, self.shares)?;
+        let Some(dir) = self.output_dir else {
+            for share in shares {
+                println!("{}", share.encode(self.encoding));
+            }
+            return Ok(());
+        };
+        for share in shares {
+            let path = dir.join(format!("share-{}-of-{}.txt", share.index(), self.shares));
+            write_key_file(
+                &path,
+                format!("{}\n", share.encode(self.encoding)).as_bytes(),
+            )?;
+            println!("Share written to {}", path.display());
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for SecretCombineOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let mut shares = Vec::new();
+        for file in &self.shares {
+            let content = String::from_utf8(get_content(file)?)?;
+            for line in content.lines() {
+                if !line.trim().is_empty() && !line.starts_with('#') {
+                    shares.push(SecretShare::decode(line)?);
+                }
+            }
+        }
+        let secret = process_secret_combine(&shares)?;
+        match self.output {
+            Some(output) => write_key_file(output, &secret)?,
+            None => io::stdout().write_all(&secret)?,
+        }
+        Ok(())
+    }
+}
+// endregion: --- impls
@@ -1,13 +1,22 @@
-use std::{fmt, path::PathBuf, str::FromStr};
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    str::FromStr,
+};
 
 use clap::Parser;
 use enum_dispatch::enum_dispatch;
-use tokio::fs;
 
 use super::{verify_file, verify_path};
 use crate::{
-    get_content, get_reader, process_text_key_generate, process_text_sign, process_text_verify,
-    CmdExecutor,
+    encode_symmetric_key, get_content, get_passphrase, get_reader, is_sealed_key, load_key,
+    load_symmetric_key, open_key, process_key_derive, process_subkey_derive, process_text_decrypt,
+    process_text_encrypt, process_text_key_generate, process_text_sign, process_text_sign_dir,
+    process_text_sign_envelope, process_text_verify, process_text_verify_dir,
+    process_text_verify_envelope, write_file_atomic, write_key_file, CmdExecutor, KeyKind,
+    SignatureEnvelope, DEFAULT_DERIVE_CONTEXT, DIR_MANIFEST_FILE,
 };
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 
@@ -24,11 +33,36 @@ pub enum TextSubcommand {
         about = "Verify a signature with a public/session key"
     )]
     Verify(TextVerifyOpts),
+    #[command(
+        name = "sign-dir",
+        about = "Sign a manifest (path, size, BLAKE3) of every file in a directory"
+    )]
+    SignDir(TextSignDirOpts),
+    #[command(
+        name = "verify-dir",
+        about = "Verify a directory manifest and report added, removed and modified files"
+    )]
+    VerifyDir(TextVerifyDirOpts),
     #[command(
         name = "generate",
-        about = "Generate a random blake3 key or ed25519 key pair"
+        about = "Generate a random symmetric key (blake3, hmac) or a key pair"
     )]
     Generate(KeyGenerateOpts),
+    #[command(
+        name = "derive",
+        about = "Derive a 32 byte key from a passphrase (argon2id) or a subkey of a key (blake3)"
+    )]
+    Derive(TextDeriveOpts),
+    #[command(
+        name = "encrypt",
+        about = "Encrypt a text with a symmetric key or an ed25519 public key (x25519)"
+    )]
+    Encrypt(TextEncryptOpts),
+    #[command(
+        name = "decrypt",
+        about = "Decrypt a text with a symmetric key or an ed25519 private key (x25519)"
+    )]
+    Decrypt(TextDecryptOpts),
 }
 
 #[derive(Debug, Parser)]
@@ -36,36 +70,196 @@ pub struct TextSignOpts {
     // input 和 key 不能同时为 "-", 在 stdin 中会竞争
     #[arg(short, long, value_parser = verify_file, default_value = "-")]
     pub input: String,
-    #[arg(short, long, value_parser = verify_file)]
-    pub key: String,
-    #[arg(long, default_value = "blake3", value_parser = parse_text_sign_format)]
-    pub format: TextSignFormat,
+    /// Private key file, or the name / fingerprint of a keyring key
+    #[arg(short, long, required_unless_present = "derive_salt")]
+    pub key: Option<String>,
+    /// Derive the key from a passphrase with this salt instead of reading a key file
+    #[arg(long, conflicts_with = "key")]
+    pub derive_salt: Option<String>,
+    /// Context string for `--derive-salt`
+    #[arg(long, default_value = DEFAULT_DERIVE_CONTEXT, requires = "derive_salt")]
+    pub context: String,
+    /// Defaults to the algorithm of a keyring key, otherwise blake3
+    #[arg(long, value_parser = parse_text_sign_format)]
+    pub format: Option<TextSignFormat>,
+    /// Signature file, defaults to `<input>.sig` (stdout when reading from stdin)
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+    /// Print the bare base64 signature instead of a signature file
+    #[arg(long, conflicts_with = "output")]
+    pub raw: bool,
+    /// Passphrase for an encrypted key, otherwise `RCLI_PASSPHRASE` or a prompt is used
+    #[arg(long, value_parser = verify_file)]
+    pub passphrase_file: Option<String>,
 }
 
 #[derive(Debug, Parser)]
 pub struct TextVerifyOpts {
     #[arg(short, long, value_parser = verify_file, default_value = "-")]
     pub input: String,
-    #[arg(short, long, value_parser = verify_file)]
+    /// Public key file, or the name / fingerprint of a keyring key
+    #[arg(short, long, required_unless_present = "derive_salt")]
+    pub key: Option<String>,
+    /// Derive the key from a passphrase with this salt instead of reading a key file
+    #[arg(long, conflicts_with = "key")]
+    pub derive_salt: Option<String>,
+    /// Context string for `--derive-salt`
+    #[arg(long, default_value = DEFAULT_DERIVE_CONTEXT, requires = "derive_salt")]
+    pub context: String,
+    /// Passphrase for `--derive-salt` or an encrypted blake3 / hmac key
+    #[arg(long, value_parser = verify_file)]
+    pub passphrase_file: Option<String>,
+    /// Bare base64 signature, as printed by `text sign --raw`
+    #[arg(long, conflicts_with = "sig_file")]
+    pub sig: Option<String>,
+    /// Signature file, defaults to `<input>.sig`
+    #[arg(long, value_parser = verify_file)]
+    pub sig_file: Option<String>,
+    /// Only needed with `--sig`, the signature file records its own algorithm
+    #[arg(long, value_parser = parse_text_sign_format)]
+    pub format: Option<TextSignFormat>,
+}
+
+#[derive(Debug, Parser)]
+pub struct TextSignDirOpts {
+    #[arg(value_parser = verify_path)]
+    pub dir: PathBuf,
+    /// Private key file, or the name / fingerprint of a keyring key
+    #[arg(short, long)]
     pub key: String,
-    #[arg(long)]
-    pub sig: String,
-    #[arg(long, default_value = "blake3", value_parser = parse_text_sign_format)]
-    pub format: TextSignFormat,
+    /// Defaults to the algorithm of a keyring key, otherwise blake3
+    #[arg(long, value_parser = parse_text_sign_format)]
+    pub format: Option<TextSignFormat>,
+    /// Manifest file, defaults to `<dir>/MANIFEST.rcli.json`. The signature is `<manifest>.sig`
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+    #[arg(long, value_parser = verify_file)]
+    pub passphrase_file: Option<String>,
+}
+
+#[derive(Debug, Parser)]
+pub struct TextVerifyDirOpts {
+    #[arg(value_parser = verify_path)]
+    pub dir: PathBuf,
+    /// Public key file, or the name / fingerprint of a keyring key
+    #[arg(short, long)]
+    pub key: String,
+    /// Manifest file, defaults to `<dir>/MANIFEST.rcli.json`
+    #[arg(short, long, value_parser = verify_file)]
+    pub manifest: Option<String>,
+    /// Signature file, defaults to `<manifest>.sig`
+    #[arg(long, value_parser = verify_file)]
+    pub sig_file: Option<String>,
+    /// Passphrase for an encrypted blake3 / hmac key
+    #[arg(long, value_parser = verify_file)]
+    pub passphrase_file: Option<String>,
 }
 
 #[derive(Debug, Parser)]
 pub struct KeyGenerateOpts {
     #[arg(long, default_value = "blake3", value_parser = parse_text_sign_format)]
     pub format: TextSignFormat,
+    /// Key file format: raw, pem (PKCS#8/SPKI), der or openssh (ed25519 only).
+    /// Defaults to raw for symmetric and ed25519 keys, pem otherwise
+    #[arg(long, value_parser = parse_key_format)]
+    pub key_format: Option<KeyFormat>,
     #[arg(short, long, value_parser = verify_path)]
     pub output_path: PathBuf,
+    /// Encrypt the private key with a passphrase (argon2id + XChaCha20-Poly1305)
+    #[arg(long)]
+    pub encrypt: bool,
+    #[arg(long, value_parser = verify_file, requires = "encrypt")]
+    pub passphrase_file: Option<String>,
+}
+
+#[derive(Debug, Parser)]
+pub struct TextDeriveOpts {
+    /// Salt for passphrase derivation, at least 8 bytes. Anyone deriving the same key needs it
+    #[arg(long, required_unless_present = "key")]
+    pub salt: Option<String>,
+    /// Derive a subkey of this blake3 key file (or keyring key) instead of a passphrase
+    #[arg(short, long, conflicts_with = "salt")]
+    pub key: Option<String>,
+    /// Context string, e.g. "myapp 2024-06-01 session tokens". Different contexts give
+    /// independent keys
+    #[arg(long, default_value = DEFAULT_DERIVE_CONTEXT)]
+    pub context: String,
+    #[arg(long, default_value = "base64", value_parser = parse_key_encoding)]
+    pub encoding: KeyEncoding,
+    /// Key file, defaults to stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+    #[arg(long, value_parser = verify_file)]
+    pub passphrase_file: Option<String>,
+}
+
+#[derive(Debug, Parser)]
+pub struct TextEncryptOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// 32 byte symmetric key, or the recipient's ed25519 public key for x25519
+    #[arg(short, long, value_parser = verify_file)]
+    pub key: String,
+    /// Output file, defaults to stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+    #[arg(long, default_value = "xchacha20poly1305", value_parser = parse_text_crypt_format)]
+    pub format: TextCryptFormat,
+    /// Passphrase for an encrypted symmetric key
+    #[arg(long, value_parser = verify_file)]
+    pub passphrase_file: Option<String>,
+}
+
+#[derive(Debug, Parser)]
+pub struct TextDecryptOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// 32 byte symmetric key, or your ed25519 private key for x25519
+    #[arg(short, long, value_parser = verify_file)]
+    pub key: String,
+    /// Output file, only written when the whole message decrypts successfully. Defaults to stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+    #[arg(long, value_parser = verify_file)]
+    pub passphrase_file: Option<String>,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TextSignFormat {
     Blake3,
     Ed25519,
+    /// prehashed Ed25519 (SHA-512), streams the input
+    Ed25519ph,
+    EcdsaP256,
+    EcdsaSecp256k1,
+    /// RSASSA-PSS with SHA-256
+    RsaPss,
+    HmacSha256,
+    HmacSha512,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextCryptFormat {
+    ChaCha20Poly1305,
+    XChaCha20Poly1305,
+    /// x25519 key agreement with the ed25519 key pair, then XChaCha20-Poly1305
+    X25519,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyFormat {
+    Raw,
+    Pem,
+    Der,
+    OpenSsh,
+}
+
+/// text encoding of generated and derived symmetric keys, recorded as a `hex:` / `base64:`
+/// prefix in the key file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyEncoding {
+    Hex,
+    Base64,
 }
 
 // region:    --- impls
@@ -76,6 +270,12 @@ impl FromStr for TextSignFormat {
         match s {
             "blake3" => Ok(TextSignFormat::Blake3),
             "ed25519" => Ok(TextSignFormat::Ed25519),
+            "ed25519ph" => Ok(TextSignFormat::Ed25519ph),
+            "p256" => Ok(TextSignFormat::EcdsaP256),
+            "secp256k1" => Ok(TextSignFormat::EcdsaSecp256k1),
+            "rsa-pss" => Ok(TextSignFormat::RsaPss),
+            "hmac-sha256" => Ok(TextSignFormat::HmacSha256),
+            "hmac-sha512" => Ok(TextSignFormat::HmacSha512),
             _ => Err(anyhow::anyhow!("Invalid format")),
         }
     }
@@ -86,6 +286,12 @@ impl From<TextSignFormat> for &'static str {
         match format {
             TextSignFormat::Blake3 => "blake3",
             TextSignFormat::Ed25519 => "ed25519",
+            TextSignFormat::Ed25519ph => "ed25519ph",
+            TextSignFormat::EcdsaP256 => "p256",
+            TextSignFormat::EcdsaSecp256k1 => "secp256k1",
+            TextSignFormat::RsaPss => "rsa-pss",
+            TextSignFormat::HmacSha256 => "hmac-sha256",
+            TextSignFormat::HmacSha512 => "hmac-sha512",
         }
     }
 }
@@ -96,14 +302,135 @@ impl fmt::Display for TextSignFormat {
     }
 }
 
+impl TextSignFormat {
+    /// the same key signs and verifies
+    pub fn is_symmetric(&self) -> bool {
+        matches!(
+            self,
+            TextSignFormat::Blake3 | TextSignFormat::HmacSha256 | TextSignFormat::HmacSha512
+        )
+    }
+
+    pub fn default_key_format(&self) -> KeyFormat {
+        match self {
+            TextSignFormat::EcdsaP256 | TextSignFormat::EcdsaSecp256k1 | TextSignFormat::RsaPss => {
+                KeyFormat::Pem
+            }
+            _ => KeyFormat::Raw,
+        }
+    }
+}
+
+impl FromStr for TextCryptFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "chacha20poly1305" => Ok(TextCryptFormat::ChaCha20Poly1305),
+            "xchacha20poly1305" => Ok(TextCryptFormat::XChaCha20Poly1305),
+            "x25519" => Ok(TextCryptFormat::X25519),
+            _ => Err(anyhow::anyhow!("Invalid format")),
+        }
+    }
+}
+
+impl From<TextCryptFormat> for &'static str {
+    fn from(format: TextCryptFormat) -> Self {
+        match format {
+            TextCryptFormat::ChaCha20Poly1305 => "chacha20poly1305",
+            TextCryptFormat::XChaCha20Poly1305 => "xchacha20poly1305",
+            TextCryptFormat::X25519 => "x25519",
+        }
+    }
+}
+
+impl fmt::Display for TextCryptFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl FromStr for KeyFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(KeyFormat::Raw),
+            "pem" => Ok(KeyFormat::Pem),
+            "der" => Ok(KeyFormat::Der),
+            "openssh" => Ok(KeyFormat::OpenSsh),
+            _ => Err(anyhow::anyhow!("Invalid key format")),
+        }
+    }
+}
+
+impl From<KeyFormat> for &'static str {
+    fn from(format: KeyFormat) -> Self {
+        match format {
+            KeyFormat::Raw => "raw",
+            KeyFormat::Pem => "pem",
+            KeyFormat::Der => "der",
+            KeyFormat::OpenSsh => "openssh",
+        }
+    }
+}
+
+impl fmt::Display for KeyFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl FromStr for KeyEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hex" => Ok(KeyEncoding::Hex),
+            "base64" => Ok(KeyEncoding::Base64),
+            _ => Err(anyhow::anyhow!("Invalid key encoding")),
+        }
+    }
+}
+
+impl From<KeyEncoding> for &'static str {
+    fn from(encoding: KeyEncoding) -> Self {
+        match encoding {
+            KeyEncoding::Hex => "hex",
+            KeyEncoding::Base64 => "base64",
+        }
+    }
+}
+
+impl fmt::Display for KeyEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
 impl CmdExecutor for TextSignOpts {
     async fn execute(self) -> anyhow::Result<()> {
         let mut reader = get_reader(&self.input)?;
-        let key = get_content(&self.key)?;
-        let sig = process_text_sign(&mut reader, &key, self.format)?;
-        // base64 output
-        let encoded = URL_SAFE_NO_PAD.encode(sig);
-        println!("{}", encoded);
+        let (key, format) = self.signing_key()?;
+        if self.raw {
+            let sig = process_text_sign(&mut reader, &key, format)?;
+            // base64 output
+            let encoded = URL_SAFE_NO_PAD.encode(sig);
+            println!("{}", encoded);
+            return Ok(());
+        }
+
+        let envelope = process_text_sign_envelope(&mut reader, &key, format)?;
+        let output = match self.output {
+            Some(output) => output,
+            None if self.input != "-" => format!("{}.sig", self.input).into(),
+            None => {
+                println!("{}", envelope.to_json()?);
+                return Ok(());
+            }
+        };
+        envelope.save(&output)?;
+        println!("Signature written to {}", output.display());
         Ok(())
     }
 }
@@ -111,9 +438,29 @@ impl CmdExecutor for TextSignOpts {
 impl CmdExecutor for TextVerifyOpts {
     async fn execute(self) -> anyhow::Result<()> {
         let mut reader = get_reader(&self.input)?;
-        let key = get_content(&self.key)?;
-        let decoded = URL_SAFE_NO_PAD.decode(&self.sig)?;
-        let verified = process_text_verify(&mut reader, &key, &decoded, self.format)?;
+        let (key, key_format) = self.verifying_key()?;
+        let verified = if let Some(sig) = &self.sig {
+            let decoded = URL_SAFE_NO_PAD.decode(sig)?;
+            let format = self.format.or(key_format).unwrap_or(TextSignFormat::Blake3);
+            process_text_verify(&mut reader, &key, &decoded, format)?
+        } else {
+            let sig_file = match self.sig_file {
+                Some(sig_file) => sig_file,
+                None if self.input != "-" => format!("{}.sig", self.input),
+                None => anyhow::bail!("--sig or --sig-file is required when reading from stdin"),
+            };
+            let envelope = SignatureEnvelope::load(&sig_file)?;
+            if let Some(format) = self.format {
+                if format != envelope.format()? {
+                    anyhow::bail!(
+                        "Signature file uses {}, but --format {} was given",
+                        envelope.algorithm,
+                        format
+                    );
+                }
+            }
+            process_text_verify_envelope(&mut reader, &key, &envelope)?
+        };
         if verified {
             println!("✓ Signature verified");
         } else {
@@ -123,11 +470,134 @@ impl CmdExecutor for TextVerifyOpts {
     }
 }
 
+impl CmdExecutor for TextSignDirOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let (key, key_format) = load_key(&self.key, KeyKind::Private)?;
+        let key = open_private_key(key, self.passphrase_file.as_deref())?;
+        let format = self.format.or(key_format).unwrap_or(TextSignFormat::Blake3);
+        let output = self
+            .output
+            .unwrap_or_else(|| self.dir.join(DIR_MANIFEST_FILE));
+        let sig_file = PathBuf::from(format!("{}.sig", output.display()));
+        let skip = [output.clone(), sig_file.clone()];
+        let (manifest, envelope) = process_text_sign_dir(&self.dir, &skip, &key, format)?;
+        std::fs::write(&output, &manifest)?;
+        envelope.save(&sig_file)?;
+        println!("Manifest written to {}", output.display());
+        Ok(())
+    }
+}
+
+impl CmdExecutor for TextVerifyDirOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let (key, _) = load_key(&self.key, KeyKind::Public)?;
+        let key = open_private_key(key, self.passphrase_file.as_deref())?;
+        let manifest = self
+            .manifest
+            .unwrap_or_else(|| self.dir.join(DIR_MANIFEST_FILE).display().to_string());
+        let sig_file = self.sig_file.unwrap_or_else(|| format!("{}.sig", manifest));
+        let envelope = SignatureEnvelope::load(&sig_file)?;
+        // `-` 是 stdin, 不是目录中的文件
+        let skip = [&manifest, &sig_file]
+            .into_iter()
+            .filter(|f| *f != "-")
+            .map(PathBuf::from)
+            .collect::<Vec<_>>();
+        let content = get_content(&manifest)?;
+        let report = process_text_verify_dir(&self.dir, &skip, &content, &key, &envelope)?;
+        if !report.signature_valid {
+            anyhow::bail!("⚠ Manifest signature not verified");
+        }
+        for (status, files) in [
+            ("added", &report.added),
+            ("removed", &report.removed),
+            ("modified", &report.modified),
+        ] {
+            for file in files {
+                println!("{:<9} {}", status, file);
+            }
+        }
+        if !report.is_ok() {
+            anyhow::bail!("⚠ Directory does not match the signed manifest");
+        }
+        println!("✓ Signature and all files verified");
+        Ok(())
+    }
+}
+
 impl CmdExecutor for KeyGenerateOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        let key = process_text_key_generate(self.format)?;
+        let passphrase = if self.encrypt {
+            Some(get_passphrase(self.passphrase_file.as_deref(), true)?)
+        } else {
+            None
+        };
+        let key_format = self
+            .key_format
+            .unwrap_or_else(|| self.format.default_key_format());
+        let key = process_text_key_generate(self.format, key_format, passphrase.as_deref())?;
         for (k, v) in key {
-            fs::write(self.output_path.join(k), v).await?;
+            write_key_file(self.output_path.join(k), &v)?;
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for TextDeriveOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let key = match (&self.key, &self.salt) {
+            (Some(key), _) => {
+                let (key, _) = load_key(key, KeyKind::Private)?;
+                let key = open_private_key(key, self.passphrase_file.as_deref())?;
+                process_subkey_derive(&load_symmetric_key(&key)?, &self.context)
+            }
+            (None, Some(salt)) => {
+                // 输错的 passphrase 会得到另一个 key, 需要确认
+                let passphrase = get_passphrase(self.passphrase_file.as_deref(), true)?;
+                process_key_derive(&passphrase, salt.as_bytes(), &self.context)?
+            }
+            (None, None) => anyhow::bail!("--salt or --key is required"),
+        };
+        let encoded = encode_symmetric_key(&key, self.encoding);
+        match self.output {
+            Some(output) => write_key_file(output, encoded.as_bytes())?,
+            None => print!("{}", encoded),
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for TextEncryptOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let mut reader = get_reader(&self.input)?;
+        let key = open_private_key(get_content(&self.key)?, self.passphrase_file.as_deref())?;
+        match self.output {
+            Some(output) => {
+                let mut writer = BufWriter::new(File::create(output)?);
+                process_text_encrypt(&mut reader, &mut writer, &key, self.format)?;
+                writer.flush()?;
+            }
+            None => process_text_encrypt(&mut reader, &mut io::stdout().lock(), &key, self.format)?,
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for TextDecryptOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let mut reader = get_reader(&self.input)?;
+        let key = open_private_key(get_content(&self.key)?, self.passphrase_file.as_deref())?;
+        match self.output {
+            Some(output) => {
+                // 先写到临时文件, 全部解密成功后再 rename, 被篡改的内容不会出现在 output 中
+                write_file_atomic(output, |writer| {
+                    process_text_decrypt(&mut reader, writer, &key)?;
+                    Ok(())
+                })?;
+            }
+            None => {
+                process_text_decrypt(&mut reader, &mut io::stdout().lock(), &key)?;
+            }
         }
         Ok(())
     }
@@ -135,6 +605,139 @@ impl CmdExecutor for KeyGenerateOpts {
 
 // endregion: --- impls
 
-fn parse_text_sign_format(format: &str) -> Result<TextSignFormat, anyhow::Error> {
+impl TextSignOpts {
+    fn signing_key(&self) -> anyhow::Result<(Vec<u8>, TextSignFormat)> {
+        let passphrase_file = self.passphrase_file.as_deref();
+        match (&self.key, &self.derive_salt) {
+            (Some(key), _) => {
+                let (key, key_format) = load_key(key, KeyKind::Private)?;
+                let format = self.format.or(key_format).unwrap_or(TextSignFormat::Blake3);
+                Ok((open_private_key(key, passphrase_file)?, format))
+            }
+            (None, Some(salt)) => {
+                let format = self.format.unwrap_or(TextSignFormat::Blake3);
+                Ok((
+                    derived_key(salt, &self.context, format, passphrase_file)?,
+                    format,
+                ))
+            }
+            (None, None) => anyhow::bail!("--key or --derive-salt is required"),
+        }
+    }
+}
+
+impl TextVerifyOpts {
+    fn verifying_key(&self) -> anyhow::Result<(Vec<u8>, Option<TextSignFormat>)> {
+        let passphrase_file = self.passphrase_file.as_deref();
+        match (&self.key, &self.derive_salt) {
+            // 对称算法验证时用的就是签名的 key, 可能是加密的
+            (Some(key), _) => {
+                let (key, key_format) = load_key(key, KeyKind::Public)?;
+                Ok((open_private_key(key, passphrase_file)?, key_format))
+            }
+            (None, Some(salt)) => {
+                let format = self.format.unwrap_or(TextSignFormat::Blake3);
+                let key = derived_key(salt, &self.context, format, passphrase_file)?;
+                Ok((key, Some(format)))
+            }
+            (None, None) => anyhow::bail!("--key or --derive-salt is required"),
+        }
+    }
+}
+
+// 加密的私钥需要先用 passphrase 解密
+fn open_private_key(key: Vec<u8>, passphrase_file: Option<&str>) -> anyhow::Result<Vec<u8>> {
+    if !is_sealed_key(&key) {
+        return Ok(key);
+    }
+    let passphrase = get_passphrase(passphrase_file, false)?;
+    Ok(open_key(&key, &passphrase)?)
+}
+
+// 派生出的 32 字节 key 只能用于对称算法
+fn derived_key(
+    salt: &str,
+    context: &str,
+    format: TextSignFormat,
+    passphrase_file: Option<&str>,
+) -> anyhow::Result<Vec<u8>> {
+    if !format.is_symmetric() {
+        anyhow::bail!(
+            "--derive-salt only works with blake3 and hmac, not {}",
+            format
+        );
+    }
+    let passphrase = get_passphrase(passphrase_file, false)?;
+    Ok(process_key_derive(&passphrase, salt.as_bytes(), context)?.to_vec())
+}
+
+pub(crate) fn parse_text_sign_format(format: &str) -> Result<TextSignFormat, anyhow::Error> {
+    format.parse()
+}
+
+fn parse_text_crypt_format(format: &str) -> Result<TextCryptFormat, anyhow::Error> {
+    format.parse()
+}
+
+fn parse_key_format(format: &str) -> Result<KeyFormat, anyhow::Error> {
     format.parse()
 }
+
+pub(crate) fn parse_key_encoding(encoding: &str) -> Result<KeyEncoding, anyhow::Error> {
+    encoding.parse()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sealed_symmetric_key_round_trip() -> anyhow::Result<()> {
+        let dir = std::env::temp_dir().join(format!("rcli-text-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let passphrase_file = dir.join("passphrase.txt");
+        std::fs::write(&passphrase_file, "correct horse\n")?;
+        let passphrase_file = Some(passphrase_file.display().to_string());
+
+        for format in [TextSignFormat::Blake3, TextSignFormat::HmacSha256] {
+            let keys = process_text_key_generate(format, KeyFormat::Raw, Some("correct horse"))?;
+            let (name, key) = keys.into_iter().next().unwrap();
+            assert!(is_sealed_key(&key));
+            let key_file = dir.join(name);
+            std::fs::write(&key_file, key)?;
+            let key_file = Some(key_file.display().to_string());
+
+            let sign = TextSignOpts {
+                input: "-".into(),
+                key: key_file.clone(),
+                derive_salt: None,
+                context: DEFAULT_DERIVE_CONTEXT.into(),
+                format: Some(format),
+                output: None,
+                raw: false,
+                passphrase_file: passphrase_file.clone(),
+            };
+            let (key, format) = sign.signing_key()?;
+            let envelope = process_text_sign_envelope(&mut &b"hello"[..], &key, format)?;
+
+            let verify = TextVerifyOpts {
+                input: "-".into(),
+                key: key_file,
+                derive_salt: None,
+                context: DEFAULT_DERIVE_CONTEXT.into(),
+                passphrase_file: passphrase_file.clone(),
+                sig: None,
+                sig_file: None,
+                format: None,
+            };
+            let (key, _) = verify.verifying_key()?;
+            assert!(process_text_verify_envelope(
+                &mut &b"hello"[..],
+                &key,
+                &envelope
+            )?);
+        }
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -1,13 +1,58 @@
+mod age_crypt;
 mod b64;
 mod csv_convert;
+mod dir_sign;
+mod encrypt;
+mod envelope;
+mod error;
 mod gen_pass;
+mod hash;
 mod http_serve;
 mod jwt;
+mod key_derive;
+mod key_format;
+mod keyring;
+mod sealed_key;
+mod secret_share;
+mod sig_algos;
 mod text;
 
+pub use age_crypt::{
+    process_age_decrypt, process_age_encrypt, process_age_encrypt_passphrase, process_age_keygen,
+};
 pub use b64::{process_decode, process_encode};
 pub use csv_convert::process_csv;
+pub use dir_sign::{
+    process_dir_manifest, process_text_sign_dir, process_text_verify_dir, DirManifest,
+    DirVerifyReport, ManifestEntry, DIR_MANIFEST_FILE,
+};
+pub use encrypt::{process_text_decrypt, process_text_encrypt};
+pub use envelope::{process_text_sign_envelope, process_text_verify_envelope, SignatureEnvelope};
+pub use error::ProcessError;
 pub use gen_pass::process_genpass;
-pub use http_serve::process_http_serve;
+pub use hash::{
+    collect_files, format_manifest_line, parse_manifest, process_hash, process_hash_check,
+    process_hash_file, process_hash_files, HashCheckEntry, HashCheckStatus,
+};
+pub use http_serve::{
+    cert_fingerprint, generate_self_signed_cert, http_mock_router, http_serve_router,
+    load_htpasswd, load_mock_spec, load_serve_rules, process_http_mock, process_http_serve,
+    HeaderRule, HttpAuth, HttpCors, HttpJwtAuth, HttpMockConfig, HttpPassword, HttpProxy,
+    HttpServeConfig, HttpTls, HttpUpload, HttpUser, MockRoute, MockSpec, Mount, NotFoundRule,
+    RedirectRule, RewriteRule, ServeRules,
+};
 pub use jwt::*;
-pub use text::{process_text_key_generate, process_text_sign, process_text_verify};
+pub use key_derive::{
+    decode_symmetric_key, encode_symmetric_key, generate_symmetric_key, load_symmetric_key,
+    process_key_derive, process_subkey_derive, DEFAULT_DERIVE_CONTEXT,
+};
+pub use key_format::{
+    encode_signing_key, encode_verifying_key, load_signing_key, load_verifying_key,
+};
+pub use keyring::{key_fingerprint, load_key, process_key_import, KeyEntry, KeyKind, Keyring};
+pub use sealed_key::{is_sealed_key, open_key, seal_key};
+pub use secret_share::{process_secret_combine, process_secret_split, SecretShare};
+pub use text::{
+    key_id, process_text_key_generate, process_text_sign, process_text_verify, TextSigner,
+    TextVerifier,
+};
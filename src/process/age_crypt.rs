@@ -0,0 +1,217 @@
+use std::{
+    io::{self, Read, Write},
+    iter,
+};
+
+use age::{
+    armor::{ArmoredReader, ArmoredWriter, Format},
+    scrypt,
+    secrecy::ExposeSecret,
+    secrecy::SecretString,
+    x25519, Decryptor, Encryptor, Identity, IdentityFile, Recipient,
+};
+
+use super::error::{ProcessError, Result};
+
+/// encrypt to age (age-encryption.org/v1) X25519 recipients (`age1...`), readable by age/rage
+pub fn process_age_encrypt(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    recipients: &[String],
+    armor: bool,
+) -> Result<()> {
+    let recipients = recipients
+        .iter()
+        .map(|r| parse_recipient(r))
+        .collect::<Result<Vec<_>>>()?;
+    if recipients.is_empty() {
+        return Err(ProcessError::InvalidKey(
+            "at least one recipient is required".into(),
+        ));
+    }
+    let encryptor = Encryptor::with_recipients(recipients.iter().map(|r| r as &dyn Recipient))
+        .map_err(ProcessError::other)?;
+    encrypt(encryptor, reader, writer, armor)
+}
+
+/// encrypt with a passphrase (scrypt recipient), same as `age --passphrase`
+pub fn process_age_encrypt_passphrase(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    passphrase: &str,
+    armor: bool,
+) -> Result<()> {
+    let encryptor = Encryptor::with_user_passphrase(SecretString::from(passphrase.to_string()));
+    encrypt(encryptor, reader, writer, armor)
+}
+
+/// decrypt an age file, binary or armored. `identities` are the contents of identity files
+/// (`AGE-SECRET-KEY-1...` lines); `passphrase` is only called for passphrase encrypted files
+pub fn process_age_decrypt(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    identities: &[Vec<u8>],
+    passphrase: impl FnOnce() -> Result<String>,
+) -> Result<()> {
+    let decryptor = Decryptor::new(ArmoredReader::new(reader)).map_err(decrypt_error)?;
+    let mut reader = if decryptor.is_scrypt() {
+        let identity = scrypt::Identity::new(SecretString::from(passphrase()?));
+        decryptor
+            .decrypt(iter::once(&identity as &dyn Identity))
+            .map_err(decrypt_error)?
+    } else {
+        let identities = identities
+            .iter()
+            .map(|content| {
+                IdentityFile::from_buffer(&content[..])?
+                    .into_identities()
+                    .map_err(ProcessError::invalid_key)
+            })
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        if identities.is_empty() {
+            return Err(ProcessError::InvalidKey(
+                "file is encrypted to recipients, an identity (-i) is required".into(),
+            ));
+        }
+        decryptor
+            .decrypt(identities.iter().map(|i| i.as_ref()))
+            .map_err(decrypt_error)?
+    };
+    io::copy(&mut reader, writer)?;
+    Ok(())
+}
+
+/// new X25519 identity, returns (identity file content, recipient), same layout as age-keygen
+pub fn process_age_keygen() -> (String, String) {
+    let identity = x25519::Identity::generate();
+    let recipient = identity.to_public().to_string();
+    let content = format!(
+        "# created: {}\n# public key: {}\n{}\n",
+        chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        recipient,
+        identity.to_string().expose_secret()
+    );
+    (content, recipient)
+}
+
+fn parse_recipient(recipient: &str) -> Result<x25519::Recipient> {
+    recipient
+        .trim()
+        .parse()
+        .map_err(|e| ProcessError::InvalidKey(format!("invalid recipient {}: {}", recipient, e)))
+}
+
+// 格式错误的文件也算作解密失败, 和 age 的错误信息保持一致
+fn decrypt_error(e: age::DecryptError) -> ProcessError {
+    match e {
+        age::DecryptError::Io(e) => ProcessError::Io(e),
+        e => ProcessError::DecryptionFailed(e.to_string()),
+    }
+}
+
+fn encrypt(
+    encryptor: Encryptor,
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    armor: bool,
+) -> Result<()> {
+    let format = if armor {
+        Format::AsciiArmor
+    } else {
+        Format::Binary
+    };
+    let output = ArmoredWriter::wrap_output(writer, format)?;
+    // finish 写入最后一个 chunk, 不调用的话文件会被截断
+    let mut stream = encryptor.wrap_output(output)?;
+    io::copy(reader, &mut stream)?;
+    stream.finish()?.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAINTEXT: &[u8] = include_bytes!("../../fixtures/b64.txt");
+
+    fn identity(content: &str) -> Vec<u8> {
+        content.as_bytes().to_vec()
+    }
+
+    fn no_passphrase() -> Result<String> {
+        Err(ProcessError::other(anyhow::anyhow!(
+            "passphrase should not be asked"
+        )))
+    }
+
+    #[test]
+    fn test_age_multiple_recipients() -> Result<()> {
+        let (alice, alice_pk) = process_age_keygen();
+        let (bob, bob_pk) = process_age_keygen();
+        let (eve, _) = process_age_keygen();
+        for armor in [false, true] {
+            let mut encrypted = Vec::new();
+            process_age_encrypt(
+                &mut &PLAINTEXT[..],
+                &mut encrypted,
+                &[alice_pk.clone(), bob_pk.clone()],
+                armor,
+            )?;
+            assert_eq!(
+                encrypted.starts_with(b"-----BEGIN AGE ENCRYPTED FILE-----"),
+                armor
+            );
+            for key in [&alice, &bob] {
+                let mut decrypted = Vec::new();
+                process_age_decrypt(
+                    &mut &encrypted[..],
+                    &mut decrypted,
+                    &[identity(key)],
+                    no_passphrase,
+                )?;
+                assert_eq!(decrypted, PLAINTEXT);
+            }
+            let ret = process_age_decrypt(
+                &mut &encrypted[..],
+                &mut Vec::new(),
+                &[identity(&eve)],
+                no_passphrase,
+            );
+            assert!(matches!(ret, Err(ProcessError::DecryptionFailed(_))));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_age_passphrase() -> Result<()> {
+        // 默认 work factor (2^18) 在 debug 构建下太慢
+        let mut recipient = scrypt::Recipient::new(SecretString::from("correct horse".to_string()));
+        recipient.set_work_factor(10);
+        let encryptor = Encryptor::with_recipients(iter::once(&recipient as &dyn Recipient))
+            .map_err(ProcessError::other)?;
+        let mut encrypted = Vec::new();
+        encrypt(encryptor, &mut &PLAINTEXT[..], &mut encrypted, true)?;
+
+        let mut decrypted = Vec::new();
+        process_age_decrypt(&mut &encrypted[..], &mut decrypted, &[], || {
+            Ok("correct horse".to_string())
+        })?;
+        assert_eq!(decrypted, PLAINTEXT);
+        let ret = process_age_decrypt(&mut &encrypted[..], &mut Vec::new(), &[], || {
+            Ok("battery staple".to_string())
+        });
+        assert!(ret.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_age_invalid_recipient() {
+        let ret = process_age_encrypt(&mut &b""[..], &mut Vec::new(), &["age1xyz".into()], false);
+        assert!(ret.is_err());
+        let ret = process_age_encrypt(&mut &b""[..], &mut Vec::new(), &[], false);
+        assert!(ret.is_err());
+    }
+}
@@ -1,7 +1,7 @@
 use std::io::Read;
 
+use super::error::Result;
 use crate::Base64Format;
-use anyhow::Result;
 use base64::{
     engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
     Engine as _,
@@ -40,7 +40,7 @@ mod tests {
     use crate::get_reader;
 
     #[test]
-    fn test_process_encode() -> Result<()> {
+    fn test_process_encode() -> anyhow::Result<()> {
         let input = "Cargo.toml";
         let mut reader = get_reader(input)?;
         let format = Base64Format::Standard;
@@ -49,7 +49,7 @@ mod tests {
     }
 
     #[test]
-    fn test_process_decode() -> Result<()> {
+    fn test_process_decode() -> anyhow::Result<()> {
         let input = "fixtures/b64.txt";
         let mut reader = get_reader(input)?;
         let format = Base64Format::UrlSafe;
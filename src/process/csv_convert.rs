@@ -1,8 +1,9 @@
-use std::fs;
+use std::{fs, path::Path};
 
-use csv::Reader;
+use csv::{Reader, StringRecord};
 use serde_json::Value;
 
+use super::error::{ProcessError, Result};
 use crate::OutputFormat;
 
 // use crate::cli::OutputFormat;
@@ -24,8 +25,7 @@ use crate::OutputFormat;
 //     kit_number: u8,
 // }
 
-pub fn process_csv(input: &str, output: &str, format: &OutputFormat) -> anyhow::Result<()> {
-    let mut rdr = Reader::from_path(input)?;
+pub fn process_csv(input: &str, output: &str, format: &OutputFormat) -> Result<()> {
     // ***** from for loop to map *****
     // let mut records = Vec::new();
     // for result in rdr.deserialize() {
@@ -48,10 +48,9 @@ pub fn process_csv(input: &str, output: &str, format: &OutputFormat) -> anyhow::
     //     .collect::<Value>();
 
     // ***** more universal way with for loop *****
-    let headers = rdr.headers()?.clone();
-    let mut ret = Vec::with_capacity(128);
-    for record in rdr.records() {
-        let record = record?;
+    let (headers, records) = read_csv(input)?;
+    let mut ret = Vec::with_capacity(records.len());
+    for record in records {
         // headers.iter() -> use the iterator of headers
         // record.iter() -> use the iterator of record
         // zip() -> combine the two iterators into one iterator of tuples [(header, record), ...]
@@ -62,9 +61,17 @@ pub fn process_csv(input: &str, output: &str, format: &OutputFormat) -> anyhow::
     // let json = serde_json::to_string_pretty(&ret)?;
     let content = match format {
         OutputFormat::Json => serde_json::to_string_pretty(&ret)?,
-        OutputFormat::Yaml => serde_yaml::to_string(&ret)?,
+        OutputFormat::Yaml => serde_yaml::to_string(&ret).map_err(ProcessError::bad_encoding)?,
         // OutputFormat::Toml => toml::to_string(&ret)?,
     };
     fs::write(output, content)?;
     Ok(())
 }
+
+/// header row and records of a CSV file, shared with the `http serve --render` tables
+pub(crate) fn read_csv(input: impl AsRef<Path>) -> Result<(StringRecord, Vec<StringRecord>)> {
+    let mut rdr = Reader::from_path(input)?;
+    let headers = rdr.headers()?.clone();
+    let records = rdr.records().collect::<Result<Vec<_>, _>>()?;
+    Ok((headers, records))
+}
@@ -0,0 +1,236 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Component, Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    envelope::{process_text_sign_envelope, process_text_verify_envelope},
+    error::{ProcessError, Result},
+};
+use crate::{collect_files, process_hash_files, HashAlgorithm, SignatureEnvelope, TextSignFormat};
+
+const MANIFEST_VERSION: u8 = 1;
+/// default manifest file, written into the signed directory
+pub const DIR_MANIFEST_FILE: &str = "MANIFEST.rcli.json";
+
+/// every file of a directory with its size and BLAKE3 hash, signed by `text sign-dir`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirManifest {
+    pub version: u8,
+    pub files: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// relative to the signed directory, `/` separated
+    pub path: String,
+    pub size: u64,
+    pub blake3: String,
+}
+
+/// result of `text verify-dir`, file lists are sorted
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DirVerifyReport {
+    pub signature_valid: bool,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub modified: Vec<String>,
+}
+
+impl DirVerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.signature_valid
+            && self.added.is_empty()
+            && self.removed.is_empty()
+            && self.modified.is_empty()
+    }
+}
+
+impl DirManifest {
+    pub fn from_json(content: &[u8]) -> Result<Self> {
+        let manifest: Self = serde_json::from_slice(content)?;
+        if manifest.version != MANIFEST_VERSION {
+            return Err(ProcessError::UnsupportedFormat(format!(
+                "manifest version {}",
+                manifest.version
+            )));
+        }
+        Ok(manifest)
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// hash every file under `dir` (in parallel), sorted by path. Symlinks are not followed.
+/// `skip` are the manifest and signature files, they are left out when inside `dir`
+pub fn process_dir_manifest(dir: impl AsRef<Path>, skip: &[PathBuf]) -> Result<DirManifest> {
+    let dir = dir.as_ref();
+    let skip = skipped_paths(dir, skip)?;
+    let files = collect_files(&[dir.display().to_string()], true)?;
+    let mut entries = Vec::with_capacity(files.len());
+    for (path, hash) in process_hash_files(&files, HashAlgorithm::Blake3) {
+        let relative = relative_path(dir, &path)?;
+        if skip.contains(&relative) {
+            continue;
+        }
+        entries.push(ManifestEntry {
+            path: relative,
+            size: path.metadata()?.len(),
+            blake3: hash?,
+        });
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(DirManifest {
+        version: MANIFEST_VERSION,
+        files: entries,
+    })
+}
+
+/// build the manifest of `dir` and sign its json with any `TextSigner`
+pub fn process_text_sign_dir(
+    dir: impl AsRef<Path>,
+    skip: &[PathBuf],
+    key: &[u8],
+    format: TextSignFormat,
+) -> Result<(String, SignatureEnvelope)> {
+    let manifest = process_dir_manifest(dir, skip)?.to_json()?;
+    let envelope = process_text_sign_envelope(&mut manifest.as_bytes(), key, format)?;
+    Ok((manifest, envelope))
+}
+
+/// check the manifest signature, then compare the manifest with the files in `dir`
+pub fn process_text_verify_dir(
+    dir: impl AsRef<Path>,
+    skip: &[PathBuf],
+    manifest: &[u8],
+    key: &[u8],
+    envelope: &SignatureEnvelope,
+) -> Result<DirVerifyReport> {
+    let signature_valid = process_text_verify_envelope(&mut &manifest[..], key, envelope)?;
+    if !signature_valid {
+        // 签名不对时 manifest 本身不可信, 没有必要再比较文件
+        return Ok(DirVerifyReport::default());
+    }
+    let expected = DirManifest::from_json(manifest)?;
+    let actual = process_dir_manifest(dir, skip)?;
+    let mut actual: BTreeMap<_, _> = actual
+        .files
+        .into_iter()
+        .map(|e| (e.path.clone(), e))
+        .collect();
+
+    let mut report = DirVerifyReport {
+        signature_valid,
+        ..Default::default()
+    };
+    for entry in expected.files {
+        match actual.remove(&entry.path) {
+            Some(file) if file == entry => {}
+            Some(_) => report.modified.push(entry.path),
+            None => report.removed.push(entry.path),
+        }
+    }
+    report.added = actual.into_keys().collect();
+    report.modified.sort();
+    report.removed.sort();
+    Ok(report)
+}
+
+// `skip` 中在 `dir` 里面的文件, 按 manifest 中的相对路径. 文件还不存在时按所在目录计算
+fn skipped_paths(dir: &Path, skip: &[PathBuf]) -> Result<HashSet<String>> {
+    let dir = dir.canonicalize()?;
+    let mut paths = HashSet::new();
+    for file in skip {
+        let absolute = match file.canonicalize() {
+            Ok(path) => path,
+            Err(_) => {
+                let (Some(parent), Some(name)) = (file.parent(), file.file_name()) else {
+                    continue;
+                };
+                let parent = if parent.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    parent
+                };
+                match parent.canonicalize() {
+                    Ok(parent) => parent.join(name),
+                    Err(_) => continue,
+                }
+            }
+        };
+        if absolute.starts_with(&dir) {
+            paths.insert(relative_path(&dir, &absolute)?);
+        }
+    }
+    Ok(paths)
+}
+
+// manifest 中统一使用 `/`, 在不同平台上签名和验证结果一致
+fn relative_path(dir: &Path, path: &Path) -> Result<String> {
+    let relative = path.strip_prefix(dir).map_err(ProcessError::other)?;
+    let parts = relative
+        .components()
+        .map(|c| match c {
+            Component::Normal(part) => part.to_str().ok_or_else(|| {
+                ProcessError::BadEncoding(format!("non UTF-8 file name: {}", path.display()))
+            }),
+            _ => Err(ProcessError::BadEncoding(format!(
+                "unexpected path component in {}",
+                path.display()
+            ))),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(parts.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    const KEY: &[u8] = include_bytes!("../../fixtures/blake3.txt");
+
+    #[test]
+    fn test_sign_verify_dir() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("rcli-sign-dir-{}", std::process::id()));
+        fs::create_dir_all(dir.join("bin"))?;
+        fs::write(dir.join("README.md"), "readme")?;
+        fs::write(dir.join("bin/rcli"), "binary")?;
+        fs::write(dir.join("bin/old"), "old")?;
+
+        // 自定义位置的 manifest 和签名不算目录中的文件
+        let skip = [dir.join("bin/SUMS.json"), dir.join("bin/SUMS.json.sig")];
+        let (manifest, envelope) = process_text_sign_dir(&dir, &skip, KEY, TextSignFormat::Blake3)?;
+        fs::write(&skip[0], &manifest)?;
+        envelope.save(&skip[1])?;
+        let report = process_text_verify_dir(&dir, &skip, manifest.as_bytes(), KEY, &envelope)?;
+        assert!(report.is_ok());
+        let parsed = DirManifest::from_json(manifest.as_bytes())?;
+        assert_eq!(parsed.files[0].path, "README.md");
+        assert_eq!(parsed.files[1].path, "bin/old");
+
+        fs::write(dir.join("bin/rcli"), "patched")?;
+        fs::remove_file(dir.join("bin/old"))?;
+        fs::write(dir.join("bin/new"), "new")?;
+        let report = process_text_verify_dir(&dir, &skip, manifest.as_bytes(), KEY, &envelope)?;
+        let tampered = manifest.replace("README.md", "README.txt");
+        let forged = process_text_verify_dir(&dir, &skip, tampered.as_bytes(), KEY, &envelope)?;
+        fs::remove_dir_all(&dir)?;
+
+        assert_eq!(
+            report,
+            DirVerifyReport {
+                signature_valid: true,
+                added: vec!["bin/new".to_string()],
+                removed: vec!["bin/old".to_string()],
+                modified: vec!["bin/rcli".to_string()],
+            }
+        );
+        assert!(!forged.signature_valid);
+        Ok(())
+    }
+}
@@ -0,0 +1,492 @@
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    ops::Sub,
+};
+
+use base64::{engine::general_purpose::STANDARD, read::DecoderReader, write::EncoderWriter};
+use chacha20poly1305::{
+    aead::{
+        generic_array::{typenum::U5, ArrayLength},
+        stream::{DecryptorBE32, EncryptorBE32, Nonce, StreamBE32},
+        AeadInPlace, KeyInit, Payload,
+    },
+    ChaCha20Poly1305, XChaCha20Poly1305,
+};
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use rand::{rngs::OsRng, RngCore};
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+
+use super::{
+    error::{ProcessError, Result},
+    key_format::{load_signing_key, load_verifying_key, raw_key},
+};
+use crate::{decode_symmetric_key, TextCryptFormat};
+
+// 加密后的格式 (armored base64):
+// header = MAGIC | version | algorithm | salt (16) 或 ephemeral x25519 public key (32) | nonce prefix
+// body   = STREAM (BE32) 分块, 每块 CHUNK_SIZE 明文 + 16 字节 tag, 最后一块不满 CHUNK_SIZE (可以为空)
+// header 作为每一块的 associated data, 任何修改, 截断或重排都会导致解密失败
+const MAGIC: &[u8; 8] = b"rcli-enc";
+const VERSION: u8 = 1;
+const CHUNK_SIZE: usize = 64 * 1024;
+const TAG_SIZE: usize = 16;
+const SALT_SIZE: usize = 16;
+// STREAM BE32 使用 nonce 的后 5 个字节作为 counter 和 last block flag
+const CHACHA20_NONCE_PREFIX: usize = 12 - 5;
+const XCHACHA20_NONCE_PREFIX: usize = 24 - 5;
+
+const ARMOR_BEGIN: &str = "-----BEGIN RCLI ENCRYPTED MESSAGE-----";
+const ARMOR_END: &str = "-----END RCLI ENCRYPTED MESSAGE-----";
+const ARMOR_LINE_WIDTH: usize = 64;
+
+const SYMMETRIC_CONTEXT: &str = "rcli 2024-06-25 text encrypt symmetric";
+const X25519_CONTEXT: &str = "rcli 2024-06-25 text encrypt x25519";
+
+/// encrypt `reader` into armored base64
+///
+/// `key` is a 32 byte symmetric key, or the recipient's ed25519 public key for x25519
+pub fn process_text_encrypt(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    key: &[u8],
+    format: TextCryptFormat,
+) -> Result<()> {
+    let mut header = MAGIC.to_vec();
+    header.extend([VERSION, format_id(format)]);
+    let content_key = match format {
+        TextCryptFormat::ChaCha20Poly1305 | TextCryptFormat::XChaCha20Poly1305 => {
+            let key = symmetric_key(key)?;
+            // 每条消息随机 salt 派生新的 key, 随机 nonce prefix 不会重复使用
+            let mut salt = [0u8; SALT_SIZE];
+            OsRng.fill_bytes(&mut salt);
+            header.extend(salt);
+            derive_symmetric_key(&key, &salt)
+        }
+        TextCryptFormat::X25519 => {
+            let recipient = x25519_public_key(&load_verifying_key(key)?);
+            let ephemeral = EphemeralSecret::random_from_rng(OsRng);
+            let ephemeral_pk = PublicKey::from(&ephemeral);
+            header.extend(ephemeral_pk.as_bytes());
+            let shared = ephemeral.diffie_hellman(&recipient);
+            derive_x25519_key(shared.as_bytes(), &ephemeral_pk, &recipient)
+        }
+    };
+    let mut nonce = vec![0u8; nonce_prefix_size(format)];
+    OsRng.fill_bytes(&mut nonce);
+    header.extend(&nonce);
+
+    writeln!(writer, "{}", ARMOR_BEGIN)?;
+    {
+        let mut encoder = EncoderWriter::new(LineWrap::new(&mut *writer), &STANDARD);
+        encoder.write_all(&header)?;
+        match format {
+            TextCryptFormat::ChaCha20Poly1305 => {
+                let aead = ChaCha20Poly1305::new(&content_key.into());
+                encrypt_chunks(aead, &nonce, &header, reader, &mut encoder)?;
+            }
+            _ => {
+                let aead = XChaCha20Poly1305::new(&content_key.into());
+                encrypt_chunks(aead, &nonce, &header, reader, &mut encoder)?;
+            }
+        }
+        encoder.finish()?;
+    }
+    writeln!(writer, "\n{}", ARMOR_END)?;
+    Ok(())
+}
+
+/// decrypt the output of `process_text_encrypt`, the algorithm is read from the header
+///
+/// `key` is the symmetric key, or the recipient's ed25519 private key for x25519
+pub fn process_text_decrypt(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    key: &[u8],
+) -> Result<TextCryptFormat> {
+    let mut reader = DecoderReader::new(Dearmor::new(reader), &STANDARD);
+
+    let mut header = [0u8; 10];
+    reader
+        .read_exact(&mut header)
+        .map_err(|_| not_encrypted())?;
+    if &header[..8] != MAGIC {
+        return Err(not_encrypted());
+    }
+    if header[8] != VERSION {
+        return Err(ProcessError::UnsupportedFormat(format!(
+            "encrypted message version {}",
+            header[8]
+        )));
+    }
+    let format = format_from_id(header[9])?;
+    let mut header = header.to_vec();
+
+    let content_key = match format {
+        TextCryptFormat::ChaCha20Poly1305 | TextCryptFormat::XChaCha20Poly1305 => {
+            let key = symmetric_key(key)?;
+            let salt = read_header(&mut reader, &mut header, SALT_SIZE)?;
+            derive_symmetric_key(&key, &salt)
+        }
+        TextCryptFormat::X25519 => {
+            let sk = load_signing_key(key)?;
+            let secret = x25519_secret(&sk);
+            let ephemeral_pk = read_header(&mut reader, &mut header, 32)?;
+            let ephemeral_pk = PublicKey::from(
+                <[u8; 32]>::try_from(ephemeral_pk)
+                    .map_err(|_| ProcessError::bad_encoding("invalid ephemeral key"))?,
+            );
+            let shared = secret.diffie_hellman(&ephemeral_pk);
+            let recipient = x25519_public_key(&sk.verifying_key());
+            derive_x25519_key(shared.as_bytes(), &ephemeral_pk, &recipient)
+        }
+    };
+    let nonce = read_header(&mut reader, &mut header, nonce_prefix_size(format))?;
+
+    match format {
+        TextCryptFormat::ChaCha20Poly1305 => {
+            let aead = ChaCha20Poly1305::new(&content_key.into());
+            decrypt_chunks(aead, &nonce, &header, &mut reader, writer)?;
+        }
+        _ => {
+            let aead = XChaCha20Poly1305::new(&content_key.into());
+            decrypt_chunks(aead, &nonce, &header, &mut reader, writer)?;
+        }
+    }
+    Ok(format)
+}
+
+fn encrypt_chunks<A>(
+    aead: A,
+    nonce: &[u8],
+    aad: &[u8],
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+) -> Result<()>
+where
+    A: AeadInPlace + KeyInit,
+    A::NonceSize: Sub<U5>,
+    <A::NonceSize as Sub<U5>>::Output: ArrayLength<u8>,
+{
+    let mut encryptor =
+        EncryptorBE32::from_aead(aead, Nonce::<A, StreamBE32<A>>::from_slice(nonce));
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    loop {
+        let n = read_full(reader, &mut buf)?;
+        let payload = Payload {
+            msg: &buf[..n],
+            aad,
+        };
+        if n < CHUNK_SIZE {
+            let ciphertext = encryptor.encrypt_last(payload).map_err(|_| too_large())?;
+            writer.write_all(&ciphertext)?;
+            return Ok(());
+        }
+        let ciphertext = encryptor.encrypt_next(payload).map_err(|_| too_large())?;
+        writer.write_all(&ciphertext)?;
+    }
+}
+
+fn decrypt_chunks<A>(
+    aead: A,
+    nonce: &[u8],
+    aad: &[u8],
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+) -> Result<()>
+where
+    A: AeadInPlace + KeyInit,
+    A::NonceSize: Sub<U5>,
+    <A::NonceSize as Sub<U5>>::Output: ArrayLength<u8>,
+{
+    let mut decryptor =
+        DecryptorBE32::from_aead(aead, Nonce::<A, StreamBE32<A>>::from_slice(nonce));
+    let mut buf = vec![0u8; CHUNK_SIZE + TAG_SIZE];
+    loop {
+        let n = read_full(reader, &mut buf)?;
+        let payload = Payload {
+            msg: &buf[..n],
+            aad,
+        };
+        if n < buf.len() {
+            let plaintext = decryptor.decrypt_last(payload).map_err(|_| tampered())?;
+            writer.write_all(&plaintext)?;
+            return Ok(());
+        }
+        let plaintext = decryptor.decrypt_next(payload).map_err(|_| tampered())?;
+        writer.write_all(&plaintext)?;
+    }
+}
+
+fn format_id(format: TextCryptFormat) -> u8 {
+    match format {
+        TextCryptFormat::ChaCha20Poly1305 => 1,
+        TextCryptFormat::XChaCha20Poly1305 => 2,
+        TextCryptFormat::X25519 => 3,
+    }
+}
+
+fn format_from_id(id: u8) -> Result<TextCryptFormat> {
+    match id {
+        1 => Ok(TextCryptFormat::ChaCha20Poly1305),
+        2 => Ok(TextCryptFormat::XChaCha20Poly1305),
+        3 => Ok(TextCryptFormat::X25519),
+        _ => Err(ProcessError::UnsupportedFormat(format!(
+            "encryption algorithm {}",
+            id
+        ))),
+    }
+}
+
+// x25519 使用 XChaCha20-Poly1305
+fn nonce_prefix_size(format: TextCryptFormat) -> usize {
+    match format {
+        TextCryptFormat::ChaCha20Poly1305 => CHACHA20_NONCE_PREFIX,
+        _ => XCHACHA20_NONCE_PREFIX,
+    }
+}
+
+fn tampered() -> ProcessError {
+    ProcessError::DecryptionFailed("wrong key, or the message was modified or truncated".into())
+}
+
+fn not_encrypted() -> ProcessError {
+    ProcessError::bad_encoding("not an rcli encrypted message")
+}
+
+fn read_header(reader: &mut dyn Read, header: &mut Vec<u8>, len: usize) -> Result<Vec<u8>> {
+    let mut buf = vec![0u8; len];
+    reader
+        .read_exact(&mut buf)
+        .map_err(|_| ProcessError::bad_encoding("truncated encrypted message header"))?;
+    header.extend(&buf);
+    Ok(buf)
+}
+
+// 和 read_exact 类似, 但是遇到 EOF 时返回已经读到的字节数
+fn read_full(reader: &mut dyn Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+// STREAM 只有在块计数器用完时才会加密失败
+fn too_large() -> ProcessError {
+    ProcessError::UnsupportedFormat("input too large for the STREAM construction".into())
+}
+
+fn symmetric_key(key: &[u8]) -> Result<[u8; 32]> {
+    if let Some(key) = decode_symmetric_key(key) {
+        return key;
+    }
+    raw_key(key)
+        .ok_or_else(|| ProcessError::InvalidKey("symmetric key must be at least 32 bytes".into()))
+}
+
+fn derive_symmetric_key(key: &[u8; 32], salt: &[u8]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new_derive_key(SYMMETRIC_CONTEXT);
+    hasher.update(key);
+    hasher.update(salt);
+    hasher.finalize().into()
+}
+
+fn derive_x25519_key(shared: &[u8; 32], ephemeral: &PublicKey, recipient: &PublicKey) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new_derive_key(X25519_CONTEXT);
+    hasher.update(shared);
+    hasher.update(ephemeral.as_bytes());
+    hasher.update(recipient.as_bytes());
+    hasher.finalize().into()
+}
+
+/// the x25519 public key of an ed25519 key pair (birationally equivalent Montgomery point)
+pub(crate) fn x25519_public_key(key: &VerifyingKey) -> PublicKey {
+    PublicKey::from(key.to_montgomery().to_bytes())
+}
+
+/// the x25519 secret of an ed25519 private key, matches `x25519_public_key`
+pub(crate) fn x25519_secret(key: &SigningKey) -> StaticSecret {
+    StaticSecret::from(key.to_scalar_bytes())
+}
+
+// 每 ARMOR_LINE_WIDTH 个字符换行
+struct LineWrap<W: Write> {
+    inner: W,
+    column: usize,
+}
+
+impl<W: Write> LineWrap<W> {
+    fn new(inner: W) -> Self {
+        Self { inner, column: 0 }
+    }
+}
+
+impl<W: Write> Write for LineWrap<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut rest = buf;
+        while !rest.is_empty() {
+            if self.column == ARMOR_LINE_WIDTH {
+                self.inner.write_all(b"\n")?;
+                self.column = 0;
+            }
+            let n = rest.len().min(ARMOR_LINE_WIDTH - self.column);
+            self.inner.write_all(&rest[..n])?;
+            self.column += n;
+            rest = &rest[n..];
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// 去掉 armor 的首尾行和换行, 只留下 base64 字符
+struct Dearmor<R> {
+    inner: BufReader<R>,
+    line: Vec<u8>,
+    pos: usize,
+    state: ArmorState,
+}
+
+#[derive(PartialEq)]
+enum ArmorState {
+    Begin,
+    Body,
+    End,
+}
+
+impl<R: Read> Dearmor<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner: BufReader::new(inner),
+            line: Vec::new(),
+            pos: 0,
+            state: ArmorState::Begin,
+        }
+    }
+
+    fn next_line(&mut self) -> io::Result<bool> {
+        self.line.clear();
+        self.pos = 0;
+        if self.inner.read_until(b'\n', &mut self.line)? == 0 {
+            return Ok(false);
+        }
+        while self.line.last().is_some_and(|c| c.is_ascii_whitespace()) {
+            self.line.pop();
+        }
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for Dearmor<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.line.len() {
+            if self.state == ArmorState::End || !self.next_line()? {
+                return Ok(0);
+            }
+            match self.state {
+                ArmorState::Begin if self.line == ARMOR_BEGIN.as_bytes() => {
+                    self.state = ArmorState::Body;
+                    self.pos = self.line.len();
+                }
+                ArmorState::Begin => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "missing armor header",
+                    ))
+                }
+                ArmorState::Body if self.line == ARMOR_END.as_bytes() => {
+                    self.state = ArmorState::End;
+                    return Ok(0);
+                }
+                _ => {}
+            }
+        }
+        let n = buf.len().min(self.line.len() - self.pos);
+        buf[..n].copy_from_slice(&self.line[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &[u8] = include_bytes!("../../fixtures/blake3.txt");
+
+    fn roundtrip(
+        data: &[u8],
+        key: &[u8],
+        decrypt_key: &[u8],
+        format: TextCryptFormat,
+    ) -> Result<()> {
+        let mut encrypted = Vec::new();
+        process_text_encrypt(&mut &data[..], &mut encrypted, key, format)?;
+        assert!(encrypted.starts_with(ARMOR_BEGIN.as_bytes()));
+        let mut decrypted = Vec::new();
+        let detected =
+            process_text_decrypt(&mut encrypted.as_slice(), &mut decrypted, decrypt_key)?;
+        assert_eq!(detected, format);
+        assert_eq!(decrypted, data);
+        Ok(())
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_roundtrip() -> Result<()> {
+        let sk = SigningKey::generate(&mut OsRng);
+        let pk = sk.verifying_key();
+        // 空输入, 正好一块, 多块
+        for data in [vec![], vec![1u8; CHUNK_SIZE], vec![2u8; CHUNK_SIZE * 2 + 3]] {
+            roundtrip(&data, KEY, KEY, TextCryptFormat::ChaCha20Poly1305)?;
+            roundtrip(&data, KEY, KEY, TextCryptFormat::XChaCha20Poly1305)?;
+            roundtrip(
+                &data,
+                pk.as_bytes(),
+                &sk.to_bytes(),
+                TextCryptFormat::X25519,
+            )?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_decrypt_tampered() -> Result<()> {
+        let data = vec![3u8; CHUNK_SIZE + 10];
+        let mut encrypted = Vec::new();
+        process_text_encrypt(
+            &mut &data[..],
+            &mut encrypted,
+            KEY,
+            TextCryptFormat::XChaCha20Poly1305,
+        )?;
+
+        // 修改密文中的一个 base64 字符
+        let mut tampered = encrypted.clone();
+        let pos = ARMOR_BEGIN.len() + 200;
+        tampered[pos] = if tampered[pos] == b'A' { b'B' } else { b'A' };
+        assert!(process_text_decrypt(&mut tampered.as_slice(), &mut Vec::new(), KEY).is_err());
+
+        // 错误的 key
+        let ret = process_text_decrypt(&mut encrypted.as_slice(), &mut Vec::new(), &[9u8; 32]);
+        assert!(matches!(ret, Err(ProcessError::DecryptionFailed(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_x25519_matches_ed25519_pair() {
+        let sk = SigningKey::generate(&mut OsRng);
+        let secret = x25519_secret(&sk);
+        assert_eq!(
+            PublicKey::from(&secret),
+            x25519_public_key(&sk.verifying_key())
+        );
+    }
+}
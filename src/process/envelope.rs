@@ -0,0 +1,141 @@
+use std::{fs, io::Read, path::Path};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    error::{ProcessError, Result},
+    text::{text_signer, text_verifier},
+};
+use crate::TextSignFormat;
+
+const ENVELOPE_VERSION: u8 = 1;
+
+/// Detached signature file (`<input>.sig`), records everything needed to verify it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignatureEnvelope {
+    pub version: u8,
+    pub algorithm: String,
+    pub key_id: String,
+    pub created_at: String,
+    /// url-safe base64 without padding, same as `text sign --raw`
+    pub signature: String,
+}
+
+impl SignatureEnvelope {
+    pub fn new(format: TextSignFormat, key_id: String, sig: &[u8]) -> Self {
+        Self {
+            version: ENVELOPE_VERSION,
+            algorithm: format.to_string(),
+            key_id,
+            created_at: chrono::Utc::now().to_rfc3339(),
+            signature: URL_SAFE_NO_PAD.encode(sig),
+        }
+    }
+
+    pub fn format(&self) -> Result<TextSignFormat> {
+        self.algorithm
+            .parse()
+            .map_err(|_| ProcessError::UnsupportedFormat(self.algorithm.clone()))
+    }
+
+    pub fn signature(&self) -> Result<Vec<u8>> {
+        URL_SAFE_NO_PAD
+            .decode(&self.signature)
+            .map_err(|e| ProcessError::BadSignature(e.to_string()))
+    }
+
+    pub fn from_json(content: &str) -> Result<Self> {
+        let envelope: Self = serde_json::from_str(content)?;
+        if envelope.version != ENVELOPE_VERSION {
+            return Err(ProcessError::UnsupportedFormat(format!(
+                "signature version {}",
+                envelope.version
+            )));
+        }
+        Ok(envelope)
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+}
+
+pub fn process_text_sign_envelope(
+    reader: &mut dyn Read,
+    key: &[u8],
+    format: TextSignFormat,
+) -> Result<SignatureEnvelope> {
+    let signer = text_signer(key, format)?;
+    let sig = signer.sign(reader)?;
+    Ok(SignatureEnvelope::new(format, signer.key_id(), &sig))
+}
+
+// 算法从 envelope 中读取, 不需要再传 --format
+pub fn process_text_verify_envelope(
+    reader: &mut dyn Read,
+    key: &[u8],
+    envelope: &SignatureEnvelope,
+) -> Result<bool> {
+    let verifier = text_verifier(key, envelope.format()?)?;
+    let key_id = verifier.key_id();
+    if key_id != envelope.key_id {
+        return Err(ProcessError::InvalidKey(format!(
+            "signature was made with key {}, but key {} was given",
+            envelope.key_id, key_id
+        )));
+    }
+    verifier.verify(reader, &envelope.signature()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::SigningKey;
+    use rand::rngs::OsRng;
+
+    const KEY: &[u8] = include_bytes!("../../fixtures/blake3.txt");
+
+    #[test]
+    fn test_envelope_roundtrip() -> Result<()> {
+        let sk = SigningKey::generate(&mut OsRng);
+        let pk = sk.verifying_key();
+        let envelope = process_text_sign_envelope(
+            &mut &b"hello"[..],
+            &sk.to_bytes(),
+            TextSignFormat::Ed25519,
+        )?;
+
+        let envelope = SignatureEnvelope::from_json(&envelope.to_json()?)?;
+        assert_eq!(envelope.format()?, TextSignFormat::Ed25519);
+        assert!(process_text_verify_envelope(
+            &mut &b"hello"[..],
+            pk.as_bytes(),
+            &envelope
+        )?);
+        assert!(!process_text_verify_envelope(
+            &mut &b"world"[..],
+            pk.as_bytes(),
+            &envelope
+        )?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_envelope_rejects_wrong_key() -> Result<()> {
+        let envelope = process_text_sign_envelope(&mut &b"hello"[..], KEY, TextSignFormat::Blake3)?;
+        let other = [7u8; 32];
+        let ret = process_text_verify_envelope(&mut &b"hello"[..], &other, &envelope);
+        assert!(matches!(ret, Err(ProcessError::InvalidKey(_))));
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+use std::{io, string::FromUtf8Error};
+
+use thiserror::Error;
+
+/// errors returned by the `process_*` functions, so library users can match on the kind
+#[derive(Debug, Error)]
+pub enum ProcessError {
+    #[error("Invalid key length: expected {expected} bytes, got {actual}")]
+    InvalidKeyLength { expected: usize, actual: usize },
+    #[error("Invalid key: {0}")]
+    InvalidKey(String),
+    #[error("Key not found: {0}")]
+    KeyNotFound(String),
+    #[error("Bad encoding: {0}")]
+    BadEncoding(String),
+    /// the signature can't be parsed, a well-formed but wrong signature verifies as `false`
+    #[error("Bad signature: {0}")]
+    BadSignature(String),
+    #[error("Unsupported format: {0}")]
+    UnsupportedFormat(String),
+    /// wrong key or passphrase, or the data was modified
+    #[error("Decryption failed: {0}")]
+    DecryptionFailed(String),
+    /// invalid options, `serve.toml` rules or mock specs
+    #[error("Invalid config: {0}")]
+    InvalidConfig(String),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    // 第三方库的其他错误 (jwt, tls, 模板等), 统一用 `ProcessError::other` 包装
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+pub type Result<T, E = ProcessError> = std::result::Result<T, E>;
+
+impl ProcessError {
+    pub(crate) fn invalid_key(e: impl ToString) -> Self {
+        Self::InvalidKey(e.to_string())
+    }
+
+    pub(crate) fn bad_encoding(e: impl ToString) -> Self {
+        Self::BadEncoding(e.to_string())
+    }
+
+    pub(crate) fn invalid_config(e: impl ToString) -> Self {
+        Self::InvalidConfig(e.to_string())
+    }
+
+    pub(crate) fn other(e: impl Into<anyhow::Error>) -> Self {
+        Self::Other(e.into())
+    }
+}
+
+impl From<base64::DecodeError> for ProcessError {
+    fn from(e: base64::DecodeError) -> Self {
+        Self::bad_encoding(e)
+    }
+}
+
+impl From<hex::FromHexError> for ProcessError {
+    fn from(e: hex::FromHexError) -> Self {
+        Self::bad_encoding(e)
+    }
+}
+
+impl From<FromUtf8Error> for ProcessError {
+    fn from(e: FromUtf8Error) -> Self {
+        Self::bad_encoding(e)
+    }
+}
+
+// csv 的 IO 错误单独保留, 其余都是输入格式不对
+impl From<csv::Error> for ProcessError {
+    fn from(e: csv::Error) -> Self {
+        if !e.is_io_error() {
+            return Self::bad_encoding(e);
+        }
+        match e.into_kind() {
+            csv::ErrorKind::Io(e) => Self::Io(e),
+            kind => Self::bad_encoding(format!("{:?}", kind)),
+        }
+    }
+}
+
+impl From<serde_json::Error> for ProcessError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::bad_encoding(e)
+    }
+}
@@ -0,0 +1,355 @@
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use md5::Md5;
+use rayon::prelude::*;
+use sha2::{Digest, Sha256, Sha512};
+use sha3::{Sha3_256, Sha3_512};
+use walkdir::WalkDir;
+
+use super::error::{ProcessError, Result};
+use crate::HashAlgorithm;
+
+/// streaming hasher for every `HashAlgorithm`
+enum Hasher {
+    Blake3(Box<blake3::Hasher>),
+    Sha256(Sha256),
+    Sha512(Sha512),
+    Sha3_256(Sha3_256),
+    Sha3_512(Sha3_512),
+    Md5(Md5),
+    Crc32(crc32fast::Hasher),
+}
+
+/// result of `--check` for one manifest entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HashCheckStatus {
+    Ok,
+    Mismatch,
+    Missing,
+    /// the file exists but can't be read, e.g. permission denied
+    Unreadable(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashCheckEntry {
+    pub path: String,
+    pub status: HashCheckStatus,
+}
+
+impl Hasher {
+    fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Blake3 => Self::Blake3(Box::default()),
+            HashAlgorithm::Sha256 => Self::Sha256(Sha256::new()),
+            HashAlgorithm::Sha512 => Self::Sha512(Sha512::new()),
+            HashAlgorithm::Sha3_256 => Self::Sha3_256(Sha3_256::new()),
+            HashAlgorithm::Sha3_512 => Self::Sha3_512(Sha3_512::new()),
+            HashAlgorithm::Md5 => Self::Md5(Md5::new()),
+            HashAlgorithm::Crc32 => Self::Crc32(crc32fast::Hasher::new()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Blake3(h) => {
+                h.update(data);
+            }
+            Self::Sha256(h) => h.update(data),
+            Self::Sha512(h) => h.update(data),
+            Self::Sha3_256(h) => h.update(data),
+            Self::Sha3_512(h) => h.update(data),
+            Self::Md5(h) => h.update(data),
+            Self::Crc32(h) => h.update(data),
+        }
+    }
+
+    fn finalize(self) -> Vec<u8> {
+        match self {
+            Self::Blake3(h) => h.finalize().as_bytes().to_vec(),
+            Self::Sha256(h) => h.finalize().to_vec(),
+            Self::Sha512(h) => h.finalize().to_vec(),
+            Self::Sha3_256(h) => h.finalize().to_vec(),
+            Self::Sha3_512(h) => h.finalize().to_vec(),
+            Self::Md5(h) => h.finalize().to_vec(),
+            // 和 cksum/rhash 一样按 big endian 输出
+            Self::Crc32(h) => h.finalize().to_be_bytes().to_vec(),
+        }
+    }
+}
+
+// 实现 Write 之后可以直接 io::copy, 不需要把整个文件读进内存
+impl Write for Hasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// unkeyed hash of the whole reader, returned as lowercase hex
+pub fn process_hash(reader: &mut dyn Read, algorithm: HashAlgorithm) -> Result<String> {
+    let mut hasher = Hasher::new(algorithm);
+    io::copy(reader, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+pub fn process_hash_file(path: impl AsRef<Path>, algorithm: HashAlgorithm) -> Result<String> {
+    process_hash(&mut File::open(path)?, algorithm)
+}
+
+/// expand `inputs` into files, directories are walked (sorted) when `recursive` is set
+pub fn collect_files(inputs: &[String], recursive: bool) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for input in inputs {
+        let path = Path::new(input);
+        if !path.is_dir() {
+            files.push(path.to_path_buf());
+            continue;
+        }
+        if !recursive {
+            return Err(ProcessError::InvalidConfig(format!(
+                "{} is a directory, use --recursive to hash its files",
+                input
+            )));
+        }
+        for entry in WalkDir::new(path).sort_by_file_name() {
+            let entry = entry.map_err(io::Error::from)?;
+            if entry.file_type().is_file() {
+                files.push(entry.into_path());
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// hash files in parallel, results keep the order of `files`
+pub fn process_hash_files(
+    files: &[PathBuf],
+    algorithm: HashAlgorithm,
+) -> Vec<(PathBuf, Result<String>)> {
+    files
+        .par_iter()
+        .map(|path| (path.clone(), process_hash_file(path, algorithm)))
+        .collect()
+}
+
+/// one `sha256sum` compatible line: `<hex>  <path>`. Like GNU sha256sum, a path with `\`,
+/// a newline or a carriage return is escaped and the line starts with `\`
+pub fn format_manifest_line(hash: &str, path: &Path) -> String {
+    let path = path.display().to_string();
+    if !path.contains(['\\', '\n', '\r']) {
+        return format!("{}  {}", hash, path);
+    }
+    let escaped = path
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r");
+    format!("\\{}  {}", hash, escaped)
+}
+
+/// parse a `sha256sum` style manifest, both text (`  `) and binary (` *`) modes
+pub fn parse_manifest(content: &str) -> Result<Vec<(String, String)>> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|(i, line)| {
+            let (escaped, entry) = match line.strip_prefix('\\') {
+                Some(entry) => (true, entry),
+                None => (false, line),
+            };
+            let (hash, path) = entry.split_once(' ').ok_or_else(|| invalid_line(i, line))?;
+            let path = path
+                .strip_prefix(' ')
+                .or_else(|| path.strip_prefix('*'))
+                .ok_or_else(|| invalid_line(i, line))?;
+            let path = match escaped {
+                true => unescape_path(path).ok_or_else(|| invalid_line(i, line))?,
+                false => path.to_string(),
+            };
+            Ok((hash.to_ascii_lowercase(), path))
+        })
+        .collect()
+}
+
+// `\\`, `\n` 和 `\r` 之外的转义是无效的
+fn unescape_path(path: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(path.len());
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        unescaped.push(match chars.next()? {
+            '\\' => '\\',
+            'n' => '\n',
+            'r' => '\r',
+            _ => return None,
+        });
+    }
+    Some(unescaped)
+}
+
+fn invalid_line(i: usize, line: &str) -> ProcessError {
+    ProcessError::BadEncoding(format!("invalid manifest line {}: {}", i + 1, line))
+}
+
+/// verify every manifest entry (paths relative to the current directory, like `sha256sum -c`).
+/// Without `algorithm` it is inferred from the length of each hash
+pub fn process_hash_check(
+    content: &str,
+    algorithm: Option<HashAlgorithm>,
+) -> Result<Vec<HashCheckEntry>> {
+    let entries = parse_manifest(content)?
+        .into_iter()
+        .map(|(expected, path)| {
+            let algorithm = match algorithm {
+                Some(algorithm) => algorithm,
+                None => infer_algorithm(&expected)?,
+            };
+            Ok((expected, path, algorithm))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(entries
+        .into_par_iter()
+        .map(|(expected, path, algorithm)| {
+            let status = match process_hash_file(&path, algorithm) {
+                Ok(hash) if hash == expected => HashCheckStatus::Ok,
+                Ok(_) => HashCheckStatus::Mismatch,
+                Err(ProcessError::Io(e)) if e.kind() == io::ErrorKind::NotFound => {
+                    HashCheckStatus::Missing
+                }
+                Err(e) => HashCheckStatus::Unreadable(e.to_string()),
+            };
+            HashCheckEntry { path, status }
+        })
+        .collect())
+}
+
+// 长度相同时按 sha256sum/sha512sum 的习惯选择, 其他算法需要 --algorithm
+fn infer_algorithm(hash: &str) -> Result<HashAlgorithm> {
+    match hash.len() {
+        8 => Ok(HashAlgorithm::Crc32),
+        32 => Ok(HashAlgorithm::Md5),
+        64 => Ok(HashAlgorithm::Sha256),
+        128 => Ok(HashAlgorithm::Sha512),
+        len => Err(ProcessError::BadEncoding(format!(
+            "can't tell the algorithm of a {} character hash, use --algorithm",
+            len
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn hash(data: &[u8], algorithm: HashAlgorithm) -> String {
+        process_hash(&mut &data[..], algorithm).unwrap()
+    }
+
+    #[test]
+    fn test_process_hash_known_values() {
+        assert_eq!(
+            hash(b"abc", HashAlgorithm::Sha256),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hash(b"abc", HashAlgorithm::Sha3_256),
+            "3a985da74fe225b2045c172d6bd390bd855f086e3e9d525b46bfe24511431532"
+        );
+        assert_eq!(
+            hash(b"abc", HashAlgorithm::Md5),
+            "900150983cd24fb0d6963f7d28e17f72"
+        );
+        assert_eq!(hash(b"abc", HashAlgorithm::Crc32), "352441c2");
+        assert_eq!(
+            hash(b"abc", HashAlgorithm::Blake3),
+            blake3::hash(b"abc").to_hex().to_string()
+        );
+        assert_eq!(hash(b"abc", HashAlgorithm::Sha512).len(), 128);
+        assert_eq!(hash(b"abc", HashAlgorithm::Sha3_512).len(), 128);
+    }
+
+    #[test]
+    fn test_parse_manifest() -> Result<()> {
+        let entries = parse_manifest("ABCD  a b.txt\n\n1234 *bin/c\n")?;
+        assert_eq!(
+            entries,
+            vec![
+                ("abcd".to_string(), "a b.txt".to_string()),
+                ("1234".to_string(), "bin/c".to_string())
+            ]
+        );
+        assert!(parse_manifest("nohash").is_err());
+
+        // 和 GNU sha256sum 一样转义 `\` 和换行
+        let path = Path::new("dir\\a\nb.txt");
+        let line = format_manifest_line("abcd", path);
+        assert_eq!(line, "\\abcd  dir\\\\a\\nb.txt");
+        assert_eq!(
+            parse_manifest(&line)?,
+            vec![("abcd".to_string(), "dir\\a\nb.txt".to_string())]
+        );
+        assert_eq!(
+            format_manifest_line("abcd", Path::new("a.txt")),
+            "abcd  a.txt"
+        );
+        assert!(parse_manifest("\\abcd  a\\tb").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_hash_check() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("rcli-hash-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub"))?;
+        fs::write(dir.join("a.txt"), "a")?;
+        fs::write(dir.join("sub/b.txt"), "b")?;
+
+        let files = collect_files(&[dir.display().to_string()], true)?;
+        assert_eq!(files, vec![dir.join("a.txt"), dir.join("sub/b.txt")]);
+        let manifest = process_hash_files(&files, HashAlgorithm::Sha256)
+            .into_iter()
+            .map(|(path, hash)| Ok(format_manifest_line(&hash?, &path)))
+            .collect::<Result<Vec<_>>>()?
+            .join("\n");
+
+        fs::write(dir.join("a.txt"), "changed")?;
+        fs::remove_file(dir.join("sub/b.txt"))?;
+        for algorithm in [Some(HashAlgorithm::Sha256), None] {
+            let report = process_hash_check(&manifest, algorithm)?;
+            let status = report.into_iter().map(|e| e.status).collect::<Vec<_>>();
+            assert_eq!(
+                status,
+                vec![HashCheckStatus::Mismatch, HashCheckStatus::Missing]
+            );
+        }
+
+        // 读不了的文件不是缺失
+        let unreadable = format!("{}  {}", "0".repeat(8), dir.display());
+        let report = process_hash_check(&unreadable, None)?;
+        assert!(matches!(report[0].status, HashCheckStatus::Unreadable(_)));
+        let md5 = format!(
+            "{}  {}",
+            hash(b"a", HashAlgorithm::Md5),
+            dir.join("a.txt").display()
+        );
+        fs::write(dir.join("a.txt"), "a")?;
+        assert_eq!(
+            process_hash_check(&md5, None)?[0].status,
+            HashCheckStatus::Ok
+        );
+        assert!(process_hash_check("abc  a.txt", None).is_err());
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,241 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap},
+    middleware::Next,
+    response::Response,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Local};
+use http_body::{Frame, SizeHint};
+use serde::{Serialize, Serializer};
+use std::{
+    net::SocketAddr,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Instant,
+};
+use tracing::info;
+
+use crate::AccessLogFormat;
+
+/// one finished request
+#[derive(Debug, Serialize)]
+struct AccessLogEntry {
+    #[serde(serialize_with = "serialize_time")]
+    time: DateTime<Local>,
+    remote: Option<String>,
+    /// Basic auth user, not verified here
+    user: Option<String>,
+    method: String,
+    /// path and query as requested
+    path: String,
+    version: String,
+    status: u16,
+    /// body bytes sent, after compression
+    bytes: u64,
+    latency_ms: f64,
+}
+
+/// response body that counts what is sent and logs the request once it is done
+struct LoggedBody {
+    inner: Body,
+    entry: Option<AccessLogEntry>,
+    format: AccessLogFormat,
+    start: Instant,
+}
+
+/// middleware: log every request after its body has been sent (or the client went away)
+pub(super) async fn access_log(
+    State(format): State<AccessLogFormat>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let start = Instant::now();
+    let remote = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| info.0.ip().to_string());
+    let user = basic_user(request.headers());
+    let method = request.method().to_string();
+    let path = request
+        .uri()
+        .path_and_query()
+        .map_or_else(|| request.uri().path().to_string(), |p| p.to_string());
+    let version = format!("{:?}", request.version());
+
+    let response = next.run(request).await;
+    let entry = AccessLogEntry {
+        time: Local::now(),
+        remote,
+        user,
+        method,
+        path,
+        version,
+        status: response.status().as_u16(),
+        bytes: 0,
+        latency_ms: 0.0,
+    };
+    response.map(|inner| {
+        Body::new(LoggedBody {
+            inner,
+            entry: Some(entry),
+            format,
+            start,
+        })
+    })
+}
+
+fn serialize_time<S: Serializer>(time: &DateTime<Local>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&time.to_rfc3339())
+}
+
+fn basic_user(headers: &HeaderMap) -> Option<String> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, credentials) = value.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return None;
+    }
+    let decoded = String::from_utf8(STANDARD.decode(credentials.trim()).ok()?).ok()?;
+    decoded.split_once(':').map(|(user, _)| escape_user(user))
+}
+
+// 用户名来自未验证的请求头, 换行和空格会伪造或拆开日志行, 像 Apache 一样转义成 \xhh
+fn escape_user(user: &str) -> String {
+    let mut escaped = String::with_capacity(user.len());
+    for c in user.chars() {
+        if c.is_control() || c.is_whitespace() || c == '"' || c == '\\' {
+            let mut buf = [0u8; 4];
+            for b in c.encode_utf8(&mut buf).bytes() {
+                escaped.push_str(&format!("\\x{:02x}", b));
+            }
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+// region:    --- impls
+impl AccessLogEntry {
+    /// Common Log Format, followed by the latency
+    fn common(&self) -> String {
+        let bytes = match self.bytes {
+            0 => "-".to_string(),
+            n => n.to_string(),
+        };
+        format!(
+            "{} - {} [{}] \"{} {} {}\" {} {} {:.3}ms",
+            self.remote.as_deref().unwrap_or("-"),
+            self.user.as_deref().unwrap_or("-"),
+            self.time.format("%d/%b/%Y:%H:%M:%S %z"),
+            self.method,
+            self.path,
+            self.version,
+            self.status,
+            bytes,
+            self.latency_ms
+        )
+    }
+}
+
+impl LoggedBody {
+    fn finish(&mut self) {
+        let Some(mut entry) = self.entry.take() else {
+            return;
+        };
+        entry.latency_ms = self.start.elapsed().as_secs_f64() * 1000.0;
+        match self.format {
+            AccessLogFormat::Common => info!(target: "rcli::access", "{}", entry.common()),
+            AccessLogFormat::Json => match serde_json::to_string(&entry) {
+                Ok(line) => info!(target: "rcli::access", "{}", line),
+                Err(e) => info!(target: "rcli::access", "{} ({})", entry.common(), e),
+            },
+            AccessLogFormat::Off => {}
+        }
+    }
+}
+
+impl http_body::Body for LoggedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let polled = Pin::new(&mut self.inner).poll_frame(cx);
+        match &polled {
+            Poll::Ready(Some(Ok(frame))) => {
+                if let (Some(data), Some(entry)) = (frame.data_ref(), self.entry.as_mut()) {
+                    entry.bytes += data.len() as u64;
+                }
+            }
+            Poll::Ready(Some(Err(_))) | Poll::Ready(None) => self.finish(),
+            Poll::Pending => {}
+        }
+        polled
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    // 保留 Content-Length
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+// 客户端提前断开时 body 不会读完
+impl Drop for LoggedBody {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+// endregion: --- impls
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_access_log_line() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            "Basic YWxpY2U6czNjcmV0".parse().unwrap(),
+        );
+        let entry = AccessLogEntry {
+            time: Local::now(),
+            remote: Some("127.0.0.1".to_string()),
+            user: basic_user(&headers),
+            method: "GET".to_string(),
+            path: "/a.txt?x=1".to_string(),
+            version: "HTTP/1.1".to_string(),
+            status: 200,
+            bytes: 42,
+            latency_ms: 1.5,
+        };
+        let line = entry.common();
+        assert!(line.starts_with("127.0.0.1 - alice ["));
+        assert!(line.ends_with("] \"GET /a.txt?x=1 HTTP/1.1\" 200 42 1.500ms"));
+
+        // 伪造的用户名不能拆出新的日志行
+        headers.insert(
+            header::AUTHORIZATION,
+            format!("Basic {}", STANDARD.encode("a b\n127.0.0.1 - admin:x"))
+                .parse()
+                .unwrap(),
+        );
+        assert_eq!(
+            basic_user(&headers).as_deref(),
+            Some("a\\x20b\\x0a127.0.0.1\\x20-\\x20admin")
+        );
+
+        let json: serde_json::Value = serde_json::to_value(&entry).unwrap();
+        assert_eq!(json["status"], 200);
+        assert_eq!(json["bytes"], 42);
+        assert_eq!(json["path"], "/a.txt?x=1");
+    }
+}
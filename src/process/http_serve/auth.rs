@@ -0,0 +1,247 @@
+use argon2::{Argon2, PasswordVerifier};
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashSet,
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+use subtle::ConstantTimeEq;
+use tracing::warn;
+
+use crate::{
+    process::error::{ProcessError, Result},
+    process_jwt_validate,
+};
+
+// 记住验证通过的 Basic 凭据, 免得每个请求都跑一次 bcrypt/argon2
+const MAX_VERIFIED_CACHE: usize = 1024;
+
+/// access control of `http serve`, a request passes with any of the credentials
+#[derive(Debug, Clone, Default)]
+pub struct HttpAuth {
+    /// HTTP Basic users
+    pub users: Vec<HttpUser>,
+    /// `Authorization: Bearer` tokens signed by `jwt sign`
+    pub jwt: Option<HttpJwtAuth>,
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpUser {
+    pub name: String,
+    pub password: HttpPassword,
+}
+
+/// a Basic auth password as given on the command line or stored in an htpasswd file
+#[derive(Debug, Clone)]
+pub enum HttpPassword {
+    Plain(String),
+    /// `$2y$`, `$2b$` or `$2a$` hash
+    Bcrypt(String),
+    /// PHC string, `$argon2id$v=19$...`
+    Argon2(String),
+}
+
+/// HS256 key and the claims a bearer token must carry
+#[derive(Debug, Clone)]
+pub struct HttpJwtAuth {
+    pub key: Vec<u8>,
+    pub aud: Option<String>,
+    pub sub: Option<String>,
+}
+
+#[derive(Debug)]
+pub(super) struct Authenticator {
+    auth: HttpAuth,
+    verified: Mutex<HashSet<[u8; 32]>>,
+}
+
+/// users of an htpasswd file (`user:hash` per line, `#` comments), bcrypt or argon2 hashes
+pub fn load_htpasswd(path: &Path) -> Result<Vec<HttpUser>> {
+    let content = fs::read_to_string(path)?;
+    let mut users = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((name, hash)) = line.split_once(':') else {
+            return Err(ProcessError::BadEncoding(format!(
+                "{}:{}: expected user:hash",
+                path.display(),
+                i + 1
+            )));
+        };
+        let password = if ["$2y$", "$2b$", "$2a$"].iter().any(|p| hash.starts_with(p)) {
+            HttpPassword::Bcrypt(hash.to_string())
+        } else if hash.starts_with("$argon2") {
+            HttpPassword::Argon2(hash.to_string())
+        } else {
+            // apr1/MD5, SHA1 和 crypt 都不安全, 不支持
+            return Err(ProcessError::UnsupportedFormat(format!(
+                "{}:{}: only bcrypt and argon2 hashes are supported (htpasswd -B)",
+                path.display(),
+                i + 1
+            )));
+        };
+        users.push(HttpUser {
+            name: name.to_string(),
+            password,
+        });
+    }
+    Ok(users)
+}
+
+/// middleware: 401 with `WWW-Authenticate` unless the request carries a valid credential
+pub(super) async fn require_auth(
+    State(authenticator): State<Arc<Authenticator>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let authorization = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    match authorization {
+        Some(value) if authenticator.check(&value).await => next.run(request).await,
+        Some(_) => {
+            warn!("Rejected credentials for {}", request.uri().path());
+            authenticator.unauthorized()
+        }
+        None => authenticator.unauthorized(),
+    }
+}
+
+// region:    --- impls
+impl Authenticator {
+    pub(super) fn new(auth: HttpAuth) -> Self {
+        Self {
+            auth,
+            verified: Mutex::new(HashSet::new()),
+        }
+    }
+
+    async fn check(&self, authorization: &str) -> bool {
+        let Some((scheme, credentials)) = authorization.split_once(' ') else {
+            return false;
+        };
+        let credentials = credentials.trim();
+        if scheme.eq_ignore_ascii_case("bearer") {
+            // 不缓存, token 会过期
+            return self.auth.jwt.as_ref().is_some_and(|jwt| {
+                process_jwt_validate(
+                    &jwt.key,
+                    credentials,
+                    jwt.aud.as_deref(),
+                    jwt.sub.as_deref(),
+                )
+                .unwrap_or(false)
+            });
+        }
+        if !scheme.eq_ignore_ascii_case("basic") {
+            return false;
+        }
+
+        let digest: [u8; 32] = Sha256::digest(authorization.as_bytes()).into();
+        if self.cache().contains(&digest) {
+            return true;
+        }
+        let Some((name, password)) = STANDARD
+            .decode(credentials)
+            .ok()
+            .and_then(|decoded| String::from_utf8(decoded).ok())
+            .and_then(|decoded| {
+                decoded
+                    .split_once(':')
+                    .map(|(n, p)| (n.to_string(), p.to_string()))
+            })
+        else {
+            return false;
+        };
+        let Some(user) = self.auth.users.iter().find(|u| u.name == name).cloned() else {
+            return false;
+        };
+        // bcrypt/argon2 很慢, 不要阻塞 runtime
+        let ok = tokio::task::spawn_blocking(move || user.password.verify(&password))
+            .await
+            .unwrap_or(false);
+        if ok {
+            let mut cache = self.cache();
+            if cache.len() >= MAX_VERIFIED_CACHE {
+                cache.clear();
+            }
+            cache.insert(digest);
+        }
+        ok
+    }
+
+    fn unauthorized(&self) -> Response {
+        let mut headers = HeaderMap::new();
+        if !self.auth.users.is_empty() {
+            headers.append(
+                header::WWW_AUTHENTICATE,
+                HeaderValue::from_static("Basic realm=\"rcli\", charset=\"UTF-8\""),
+            );
+        }
+        if self.auth.jwt.is_some() {
+            headers.append(
+                header::WWW_AUTHENTICATE,
+                HeaderValue::from_static("Bearer realm=\"rcli\""),
+            );
+        }
+        (StatusCode::UNAUTHORIZED, headers, "Authentication required").into_response()
+    }
+
+    fn cache(&self) -> std::sync::MutexGuard<'_, HashSet<[u8; 32]>> {
+        // 缓存内容总是完整的, 锁中毒也可以继续用
+        self.verified.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl HttpPassword {
+    fn verify(&self, password: &str) -> bool {
+        match self {
+            HttpPassword::Plain(expected) => expected.as_bytes().ct_eq(password.as_bytes()).into(),
+            HttpPassword::Bcrypt(hash) => bcrypt::verify(password, hash).unwrap_or(false),
+            HttpPassword::Argon2(hash) => argon2::PasswordHash::new(hash).is_ok_and(|hash| {
+                Argon2::default()
+                    .verify_password(password.as_bytes(), &hash)
+                    .is_ok()
+            }),
+        }
+    }
+}
+// endregion: --- impls
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use argon2::{password_hash::SaltString, PasswordHasher};
+
+    #[test]
+    fn test_password_verify() {
+        let bcrypt_hash = bcrypt::hash("s3cret", 4).unwrap();
+        let salt = SaltString::encode_b64(b"rcli-test-salt").unwrap();
+        let argon2_hash = Argon2::default()
+            .hash_password(b"s3cret", &salt)
+            .unwrap()
+            .to_string();
+        for password in [
+            HttpPassword::Plain("s3cret".to_string()),
+            HttpPassword::Bcrypt(bcrypt_hash),
+            HttpPassword::Argon2(argon2_hash),
+        ] {
+            assert!(password.verify("s3cret"));
+            assert!(!password.verify("s3cret!"));
+            assert!(!password.verify(""));
+        }
+    }
+}
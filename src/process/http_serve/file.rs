@@ -0,0 +1,185 @@
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use std::{
+    io::SeekFrom,
+    ops::Range,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+use crate::process::error::{ProcessError, Result};
+
+/// what a `Range` request header asks for
+#[derive(Debug, PartialEq, Eq)]
+enum ByteRange {
+    /// no header, a malformed one or several ranges: send the whole file
+    Full,
+    Partial(Range<u64>),
+    /// start is past the end of the file, 416
+    Unsatisfiable,
+}
+
+/// stream a regular file with its MIME type, `ETag` and `Last-Modified`,
+/// answering conditional requests with 304 and `Range` requests with 206
+pub(super) async fn serve_file(path: &Path, headers: &HeaderMap) -> Result<Response> {
+    let meta = tokio::fs::metadata(path).await?;
+    let len = meta.len();
+    let modified = meta.modified().ok();
+    let etag = etag(len, modified);
+    let last_modified = modified.map(httpdate::fmt_http_date);
+
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(header::ETAG, header_value(&etag)?);
+    if let Some(last_modified) = &last_modified {
+        response_headers.insert(header::LAST_MODIFIED, header_value(last_modified)?);
+    }
+    response_headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+
+    if not_modified(headers, &etag, modified) {
+        return Ok((StatusCode::NOT_MODIFIED, response_headers).into_response());
+    }
+
+    // If-Range 不匹配说明文件已经变了, 返回整个文件
+    let if_range_matches = match header_str(headers, header::IF_RANGE) {
+        Some(value) => value == etag || Some(value) == last_modified.as_deref(),
+        None => true,
+    };
+    let range = match header_str(headers, header::RANGE) {
+        Some(value) if if_range_matches => parse_range(value, len),
+        _ => ByteRange::Full,
+    };
+
+    let (status, range) = match range {
+        ByteRange::Full => (StatusCode::OK, 0..len),
+        ByteRange::Partial(range) => {
+            let content_range = format!("bytes {}-{}/{}", range.start, range.end - 1, len);
+            response_headers.insert(header::CONTENT_RANGE, header_value(&content_range)?);
+            (StatusCode::PARTIAL_CONTENT, range)
+        }
+        ByteRange::Unsatisfiable => {
+            let content_range = format!("bytes */{}", len);
+            response_headers.insert(header::CONTENT_RANGE, header_value(&content_range)?);
+            return Ok((StatusCode::RANGE_NOT_SATISFIABLE, response_headers).into_response());
+        }
+    };
+
+    let mime = mime_guess::from_path(path).first_or_octet_stream();
+    response_headers.insert(header::CONTENT_TYPE, header_value(mime.as_ref())?);
+    response_headers.insert(
+        header::CONTENT_LENGTH,
+        HeaderValue::from(range.end - range.start),
+    );
+
+    let mut file = tokio::fs::File::open(path).await?;
+    file.seek(SeekFrom::Start(range.start)).await?;
+    // 分块读取, 大文件不会一次读进内存
+    let body = Body::from_stream(ReaderStream::new(file.take(range.end - range.start)));
+    Ok((status, response_headers, body).into_response())
+}
+
+// 大小和修改时间任一变化都会得到新的 ETag
+fn etag(len: u64, modified: Option<SystemTime>) -> String {
+    let nanos = modified
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default()
+        .as_nanos();
+    format!("\"{:x}-{:x}\"", nanos, len)
+}
+
+fn not_modified(headers: &HeaderMap, etag: &str, modified: Option<SystemTime>) -> bool {
+    // 有 If-None-Match 时忽略 If-Modified-Since
+    if let Some(value) = header_str(headers, header::IF_NONE_MATCH) {
+        return value
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag);
+    }
+    match (header_str(headers, header::IF_MODIFIED_SINCE), modified) {
+        (Some(value), Some(modified)) => match httpdate::parse_http_date(value) {
+            // http 日期只精确到秒
+            Ok(since) => modified < since + Duration::from_secs(1),
+            Err(_) => false,
+        },
+        _ => false,
+    }
+}
+
+fn parse_range(value: &str, len: u64) -> ByteRange {
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return ByteRange::Full;
+    };
+    // 多个 range 需要 multipart 响应, 直接返回整个文件也是允许的
+    let Some((start, end)) = spec.split_once('-').filter(|_| !spec.contains(',')) else {
+        return ByteRange::Full;
+    };
+    let (start, end) = (start.trim(), end.trim());
+    if start.is_empty() {
+        // bytes=-500: 最后 500 个字节
+        return match end.parse::<u64>() {
+            Ok(0) => ByteRange::Unsatisfiable,
+            Ok(_) if len == 0 => ByteRange::Unsatisfiable,
+            Ok(suffix) => ByteRange::Partial(len.saturating_sub(suffix)..len),
+            Err(_) => ByteRange::Full,
+        };
+    }
+    let Ok(start) = start.parse::<u64>() else {
+        return ByteRange::Full;
+    };
+    let end = match end {
+        "" => len.saturating_sub(1),
+        end => match end.parse::<u64>() {
+            Ok(end) if end >= start => end.min(len.saturating_sub(1)),
+            _ => return ByteRange::Full,
+        },
+    };
+    if start >= len {
+        return ByteRange::Unsatisfiable;
+    }
+    ByteRange::Partial(start..end + 1)
+}
+
+fn header_str(headers: &HeaderMap, name: header::HeaderName) -> Option<&str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+fn header_value(value: &str) -> Result<HeaderValue> {
+    HeaderValue::from_str(value).map_err(ProcessError::other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-99", 1000), ByteRange::Partial(0..100));
+        assert_eq!(
+            parse_range("bytes=900-", 1000),
+            ByteRange::Partial(900..1000)
+        );
+        assert_eq!(
+            parse_range("bytes=-100", 1000),
+            ByteRange::Partial(900..1000)
+        );
+        assert_eq!(
+            parse_range("bytes=-5000", 1000),
+            ByteRange::Partial(0..1000)
+        );
+        assert_eq!(
+            parse_range("bytes=990-5000", 1000),
+            ByteRange::Partial(990..1000)
+        );
+        assert_eq!(parse_range("bytes=1000-", 1000), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-0", 1000), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range("bytes=0-", 0), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range("bytes=5-1", 1000), ByteRange::Full);
+        assert_eq!(parse_range("bytes=0-1,5-9", 1000), ByteRange::Full);
+        assert_eq!(parse_range("items=0-1", 1000), ByteRange::Full);
+        assert_eq!(parse_range("bytes=a-b", 1000), ByteRange::Full);
+    }
+}
@@ -0,0 +1,284 @@
+use axum::{
+    http::{header, HeaderMap},
+    response::{Html, IntoResponse, Json, Response},
+};
+use chrono::{DateTime, Local, Utc};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path, time::SystemTime};
+use tera::{Context, Tera};
+
+use crate::process::error::{ProcessError, Result};
+
+const TEMPLATE_NAME: &str = "listing.html";
+const DEFAULT_TEMPLATE: &str = include_str!("listing.html");
+// 路径中的一段, 除了 unreserved 字符都要编码
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// query of a `GET` request: `?sort=name|size|modified&order=asc|desc` for listings,
+/// `?raw` to skip `--render`
+#[derive(Debug, Default, Deserialize)]
+pub(super) struct ServeQuery {
+    #[serde(default)]
+    sort: SortKey,
+    #[serde(default)]
+    order: SortOrder,
+    pub(super) raw: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum SortKey {
+    #[default]
+    Name,
+    Size,
+    Modified,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// directory listing renderer, the built-in template or a `--template` override
+#[derive(Debug)]
+pub(super) struct Listing {
+    tera: Tera,
+    /// show the upload form
+    upload: bool,
+}
+
+/// one entry of the JSON listing
+#[derive(Debug, Serialize)]
+struct ListingEntry {
+    name: String,
+    /// absolute, percent-encoded url path
+    href: String,
+    is_dir: bool,
+    /// `None` for directories
+    size: Option<u64>,
+    /// RFC 3339, UTC
+    modified: Option<String>,
+    #[serde(skip)]
+    modified_at: Option<SystemTime>,
+}
+
+/// entry plus the display fields used by the template
+#[derive(Debug, Serialize)]
+struct EntryView<'a> {
+    #[serde(flatten)]
+    entry: &'a ListingEntry,
+    icon: &'static str,
+    size_display: String,
+    modified_display: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Breadcrumb {
+    name: String,
+    href: String,
+}
+
+impl Listing {
+    pub(super) fn new(template: Option<&Path>, upload: bool) -> Result<Self> {
+        let content = match template {
+            Some(path) => fs::read_to_string(path)?,
+            None => DEFAULT_TEMPLATE.to_string(),
+        };
+        let mut tera = Tera::default();
+        // 启动时就检查模板语法
+        tera.add_raw_template(TEMPLATE_NAME, &content)
+            .map_err(ProcessError::invalid_config)?;
+        Ok(Self { tera, upload })
+    }
+
+    /// list `dir`, served at `subpath` (relative to the root, may be empty).
+    /// Symlinks are left out unless they may be followed
+    pub(super) fn render(
+        &self,
+        dir: &Path,
+        subpath: &str,
+        query: &ServeQuery,
+        headers: &HeaderMap,
+        follow_symlinks: bool,
+    ) -> Result<Response> {
+        let segments = subpath
+            .split('/')
+            .filter(|s| !s.is_empty() && *s != ".")
+            .collect::<Vec<_>>();
+        let base = url_path(&segments);
+        let mut entries = read_entries(dir, &base, follow_symlinks)?;
+        sort_entries(&mut entries, query.sort, query.order);
+
+        if wants_json(headers) {
+            return Ok(Json(serde_json::json!({
+                "path": base,
+                "entries": entries,
+            }))
+            .into_response());
+        }
+
+        let breadcrumbs = std::iter::once(Breadcrumb {
+            name: "/".to_string(),
+            href: "/".to_string(),
+        })
+        .chain((0..segments.len()).map(|i| Breadcrumb {
+            name: segments[i].to_string(),
+            href: url_path(&segments[..=i]),
+        }))
+        .collect::<Vec<_>>();
+        let parent = (!segments.is_empty()).then(|| url_path(&segments[..segments.len() - 1]));
+        let views = entries
+            .iter()
+            .map(|entry| EntryView {
+                entry,
+                icon: icon(entry),
+                size_display: entry.size.map(human_size).unwrap_or_default(),
+                modified_display: entry
+                    .modified_at
+                    .map(|t| {
+                        DateTime::<Local>::from(t)
+                            .format("%Y-%m-%d %H:%M")
+                            .to_string()
+                    })
+                    .unwrap_or_default(),
+            })
+            .collect::<Vec<_>>();
+
+        let mut context = Context::new();
+        context.insert("path", &base);
+        context.insert("breadcrumbs", &breadcrumbs);
+        context.insert("parent", &parent);
+        context.insert("entries", &views);
+        context.insert("sort", &query.sort);
+        context.insert("order", &query.order);
+        context.insert("upload", &self.upload);
+        let html = self
+            .tera
+            .render(TEMPLATE_NAME, &context)
+            .map_err(ProcessError::other)?;
+        Ok(Html(html).into_response())
+    }
+}
+
+fn read_entries(dir: &Path, base: &str, follow_symlinks: bool) -> Result<Vec<ListingEntry>> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let mut meta = entry.metadata()?;
+        if meta.file_type().is_symlink() {
+            if !follow_symlinks {
+                continue;
+            }
+            // 失效的链接不显示
+            match fs::metadata(entry.path()) {
+                Ok(target) => meta = target,
+                Err(_) => continue,
+            }
+        }
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let is_dir = meta.is_dir();
+        let mut href = format!("{}{}", base, utf8_percent_encode(&name, PATH_SEGMENT));
+        if is_dir {
+            href.push('/');
+        }
+        let modified_at = meta.modified().ok();
+        entries.push(ListingEntry {
+            name,
+            href,
+            is_dir,
+            size: (!is_dir).then_some(meta.len()),
+            modified: modified_at.map(|t| DateTime::<Utc>::from(t).to_rfc3339()),
+            modified_at,
+        });
+    }
+    Ok(entries)
+}
+
+// 目录总是排在文件前面
+fn sort_entries(entries: &mut [ListingEntry], key: SortKey, order: SortOrder) {
+    entries.sort_by(|a, b| {
+        let ordering = match key {
+            SortKey::Name => a.name.cmp(&b.name),
+            SortKey::Size => a.size.cmp(&b.size).then_with(|| a.name.cmp(&b.name)),
+            SortKey::Modified => a
+                .modified_at
+                .cmp(&b.modified_at)
+                .then_with(|| a.name.cmp(&b.name)),
+        };
+        let ordering = match order {
+            SortOrder::Asc => ordering,
+            SortOrder::Desc => ordering.reverse(),
+        };
+        b.is_dir.cmp(&a.is_dir).then(ordering)
+    });
+}
+
+/// `/a/b/` for `["a", "b"]`, `/` for the root
+pub(super) fn url_path(segments: &[&str]) -> String {
+    segments.iter().fold("/".to_string(), |mut path, segment| {
+        path.push_str(&utf8_percent_encode(segment, PATH_SEGMENT).to_string());
+        path.push('/');
+        path
+    })
+}
+
+pub(super) fn wants_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("application/json"))
+}
+
+fn icon(entry: &ListingEntry) -> &'static str {
+    if entry.is_dir {
+        return "📁";
+    }
+    let mime = mime_guess::from_path(&entry.name).first_or_octet_stream();
+    match (mime.type_().as_str(), mime.subtype().as_str()) {
+        ("image", _) => "🖼️",
+        ("video", _) => "🎬",
+        ("audio", _) => "🎵",
+        ("text", _) => "📄",
+        ("application", "pdf") => "📕",
+        ("application", "zip" | "gzip" | "x-tar" | "x-7z-compressed" | "vnd.rar" | "x-bzip2") => {
+            "📦"
+        }
+        _ => "📃",
+    }
+}
+
+fn human_size(size: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if size < 1024 {
+        return format!("{} B", size);
+    }
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_url_path_and_size() {
+        assert_eq!(url_path(&[]), "/");
+        assert_eq!(url_path(&["a b", "c#d"]), "/a%20b/c%23d/");
+        assert_eq!(human_size(512), "512 B");
+        assert_eq!(human_size(1536), "1.5 KiB");
+        assert_eq!(human_size(5 * 1024 * 1024 * 1024), "5.0 GiB");
+    }
+}
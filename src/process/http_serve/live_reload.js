@@ -0,0 +1,17 @@
+// rcli http serve --watch
+(() => {
+  const events = new EventSource("/__rcli/livereload");
+  events.addEventListener("reload", () => location.reload());
+  // 只换样式表, 不刷新页面
+  events.addEventListener("css", () => {
+    for (const link of document.querySelectorAll('link[rel="stylesheet"]')) {
+      const url = new URL(link.href);
+      if (url.origin !== location.origin) continue;
+      url.searchParams.set("livereload", Date.now());
+      const next = link.cloneNode();
+      next.href = url.href;
+      next.onload = () => link.remove();
+      link.after(next);
+    }
+  });
+})();
@@ -0,0 +1,289 @@
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    http::{header, Method, StatusCode},
+    middleware::Next,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+};
+use futures::Stream;
+use http_body::Body as _;
+use notify::{event::ModifyKind, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    convert::Infallible,
+    fmt,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::{broadcast, mpsc, watch};
+use tracing::{debug, warn};
+
+use super::HttpServeState;
+use crate::process::error::{ProcessError, Result};
+
+/// SSE endpoint the injected script connects to
+pub(super) const EVENTS_PATH: &str = "/__rcli/livereload";
+// 编辑器保存时常常连着写好几次
+const DEBOUNCE: Duration = Duration::from_millis(150);
+// 更大的 HTML 不注入
+const MAX_INJECT_SIZE: u64 = 8 * 1024 * 1024;
+const SCRIPT: &str = concat!("<script>\n", include_str!("live_reload.js"), "</script>\n");
+
+/// files that changed within one debounce window, as url paths
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Change {
+    /// only stylesheets, swapped without a reload
+    Css(Vec<String>),
+    Reload(Vec<String>),
+}
+
+/// `--watch`: watches the root and pushes changes to open pages
+pub(super) struct LiveReload {
+    changes: broadcast::Sender<Change>,
+    /// ends the event streams on shutdown, they never finish by themselves
+    closed: watch::Sender<bool>,
+    // drop 时停止监听, 去抖任务也随之退出
+    _watcher: RecommendedWatcher,
+}
+
+impl LiveReload {
+    /// start watching the (url prefix, canonical directory) pairs,
+    /// must be called inside a tokio runtime
+    pub(super) fn start(roots: &[(String, PathBuf)]) -> Result<Arc<Self>> {
+        let (raw_tx, mut raw_rx) = mpsc::unbounded_channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+                Ok(event) => {
+                    let _ = raw_tx.send(event);
+                }
+                Err(e) => warn!("File watcher error: {}", e),
+            })
+            .map_err(ProcessError::other)?;
+        for (_, dir) in roots {
+            watcher
+                .watch(dir, RecursiveMode::Recursive)
+                .map_err(ProcessError::other)?;
+        }
+
+        let (changes, _) = broadcast::channel(16);
+        let (closed, _) = watch::channel(false);
+        let tx = changes.clone();
+        let mut roots = roots.to_vec();
+        // 挂载的目录可能在根目录里面, 先匹配更深的
+        roots.sort_by_key(|(_, dir)| std::cmp::Reverse(dir.components().count()));
+        tokio::spawn(async move {
+            while let Some(event) = raw_rx.recv().await {
+                let mut paths = changed_paths(&roots, &event);
+                loop {
+                    match tokio::time::timeout(DEBOUNCE, raw_rx.recv()).await {
+                        Ok(Some(event)) => paths.extend(changed_paths(&roots, &event)),
+                        Ok(None) => return,
+                        Err(_) => break,
+                    }
+                }
+                paths.sort();
+                paths.dedup();
+                if paths.is_empty() {
+                    continue;
+                }
+                debug!("Changed: {:?}", paths);
+                let change = if paths.iter().all(|p| p.ends_with(".css")) {
+                    Change::Css(paths)
+                } else {
+                    Change::Reload(paths)
+                };
+                // 没有打开的页面时 send 会失败
+                let _ = tx.send(change);
+            }
+        });
+
+        Ok(Arc::new(Self {
+            changes,
+            closed,
+            _watcher: watcher,
+        }))
+    }
+
+    /// end all event streams so graceful shutdown doesn't wait for them
+    pub(super) fn close(&self) {
+        self.closed.send_replace(true);
+    }
+
+    fn subscribe(&self) -> impl Stream<Item = Result<Event, Infallible>> {
+        let state = (self.changes.subscribe(), self.closed.subscribe());
+        futures::stream::unfold(state, |(mut changes, mut closed)| async move {
+            let event = tokio::select! {
+                change = changes.recv() => match change {
+                    Ok(change) => change.event(),
+                    // 落后太多, 直接整页刷新
+                    Err(broadcast::error::RecvError::Lagged(_)) => {
+                        Change::Reload(Vec::new()).event()
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                },
+                _ = closed.wait_for(|closed| *closed) => return None,
+            };
+            Some((Ok(event), (changes, closed)))
+        })
+    }
+}
+
+/// `GET /__rcli/livereload`: `reload` and `css` events with the changed paths as JSON
+pub(super) async fn events(State(state): State<Arc<HttpServeState>>) -> Response {
+    match &state.live_reload {
+        Some(live_reload) => Sse::new(live_reload.subscribe())
+            .keep_alive(KeepAlive::default())
+            .into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// middleware: add the reload script to complete HTML pages
+pub(super) async fn inject_script(request: Request, next: Next) -> Response {
+    let head = request.method() == Method::HEAD;
+    let response = next.run(request).await;
+    let is_html = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/html"));
+    // 304, Range 和已经压缩的响应保持原样
+    if head
+        || response.status() != StatusCode::OK
+        || !is_html
+        || response.headers().contains_key(header::CONTENT_ENCODING)
+    {
+        return response;
+    }
+    let length = response
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .or_else(|| response.body().size_hint().exact());
+    if length.is_none_or(|len| len > MAX_INJECT_SIZE) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, MAX_INJECT_SIZE as usize).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!("Failed to read the page for live reload: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    parts.headers.remove(header::CONTENT_LENGTH);
+    // 偏移量和注入后的内容对不上
+    parts.headers.remove(header::ACCEPT_RANGES);
+    Response::from_parts(parts, Body::from(inject(&bytes)))
+}
+
+/// the script goes before the last `</body>`, or at the end of fragments without one
+fn inject(html: &[u8]) -> Vec<u8> {
+    let pos = html
+        .windows(7)
+        .rposition(|w| w.eq_ignore_ascii_case(b"</body>"))
+        .unwrap_or(html.len());
+    let mut out = Vec::with_capacity(html.len() + SCRIPT.len());
+    out.extend_from_slice(&html[..pos]);
+    out.extend_from_slice(SCRIPT.as_bytes());
+    out.extend_from_slice(&html[pos..]);
+    out
+}
+
+/// url paths of the files an event is about. Hidden files (editor swap files, `.git`,
+/// upload temp files) and backups ending in `~` are ignored, as are access and metadata events
+fn changed_paths(roots: &[(String, PathBuf)], event: &notify::Event) -> Vec<String> {
+    match event.kind {
+        EventKind::Create(_) | EventKind::Remove(_) => {}
+        EventKind::Modify(kind) if !matches!(kind, ModifyKind::Metadata(_)) => {}
+        _ => return Vec::new(),
+    }
+    event
+        .paths
+        .iter()
+        .filter_map(|path| {
+            roots.iter().find_map(|(prefix, dir)| {
+                url_path(dir, path).map(|url| format!("{}{}", prefix, url))
+            })
+        })
+        .collect()
+}
+
+fn url_path(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    let mut url = String::new();
+    for component in relative.components() {
+        let Component::Normal(name) = component else {
+            return None;
+        };
+        let name = name.to_string_lossy();
+        if name.starts_with('.') || name.ends_with('~') {
+            return None;
+        }
+        url.push('/');
+        url.push_str(&name);
+    }
+    (!url.is_empty()).then_some(url)
+}
+
+// region:    --- impls
+impl Change {
+    fn event(&self) -> Event {
+        let (name, paths) = match self {
+            Change::Css(paths) => ("css", paths),
+            Change::Reload(paths) => ("reload", paths),
+        };
+        Event::default()
+            .event(name)
+            .data(serde_json::to_string(paths).unwrap_or_default())
+    }
+}
+
+impl fmt::Debug for LiveReload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LiveReload")
+            .field("subscribers", &self.changes.receiver_count())
+            .finish()
+    }
+}
+// endregion: --- impls
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_live_reload_paths() {
+        let root = vec![
+            ("/assets".to_string(), PathBuf::from("/srv/shared")),
+            (String::new(), PathBuf::from("/srv/site")),
+        ];
+        let site = PathBuf::from("/srv/site");
+        let event = |kind, path: &str| notify::Event::new(kind).add_path(site.join(path));
+        let modify = EventKind::Modify(ModifyKind::Any);
+
+        assert_eq!(
+            changed_paths(&root, &event(modify, "css/app.css")),
+            ["/css/app.css"]
+        );
+        assert_eq!(
+            changed_paths(&root, &event(modify, "/srv/shared/logo.svg")),
+            ["/assets/logo.svg"]
+        );
+        assert!(changed_paths(&root, &event(modify, ".git/index")).is_empty());
+        assert!(changed_paths(&root, &event(modify, ".index.html.swp")).is_empty());
+        assert!(changed_paths(&root, &event(modify, "index.html~")).is_empty());
+        let chmod = EventKind::Modify(ModifyKind::Metadata(notify::event::MetadataKind::Any));
+        assert!(changed_paths(&root, &event(chmod, "index.html")).is_empty());
+
+        let page = inject(b"<html><body><p>hi</p></BODY></html>");
+        let page = String::from_utf8(page).unwrap();
+        assert!(page.starts_with("<html><body><p>hi</p><script>"));
+        assert!(page.ends_with("</script>\n</BODY></html>"));
+    }
+}
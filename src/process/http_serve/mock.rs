@@ -0,0 +1,394 @@
+use axum::{
+    body::Bytes,
+    extract::{Query, RawPathParams},
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri},
+    response::{IntoResponse, Json, Response},
+    routing::{MethodFilter, MethodRouter},
+    Router,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+use tera::{Context, Tera};
+use tracing::warn;
+
+use crate::process::error::{ProcessError, Result};
+
+/// canned responses of `http mock --spec routes.yaml`, in YAML or JSON
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MockSpec {
+    pub routes: Vec<MockRoute>,
+}
+
+/// one mocked endpoint. String values of `json`/`yaml` and the whole `body` are tera
+/// templates with the path parameters, `params`, `query`, `method`, `path` and the
+/// request `body` (parsed when it is JSON)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MockRoute {
+    /// `GET`, `POST`, ... or `ANY`
+    #[serde(default = "default_method")]
+    pub method: String,
+    /// axum syntax, `/users/:id` or `/files/*rest`
+    pub path: String,
+    #[serde(default = "default_status")]
+    pub status: u16,
+    /// `300ms`, `2s`
+    pub delay: Option<String>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// sent as `application/json`
+    pub json: Option<Value>,
+    /// sent as `application/yaml`
+    pub yaml: Option<Value>,
+    /// sent as is, `text/plain` unless `headers` say otherwise
+    pub body: Option<String>,
+}
+
+#[derive(Debug)]
+enum MockBody {
+    Empty,
+    /// template named `body`
+    Text,
+    Json(Value),
+    Yaml(Value),
+}
+
+/// a route ready to answer, its templates compiled
+#[derive(Debug)]
+struct Mock {
+    status: StatusCode,
+    delay: Option<Duration>,
+    headers: HeaderMap,
+    body: MockBody,
+    tera: Tera,
+    /// JSON pointers of the `json`/`yaml` strings that are templates
+    templated: HashSet<String>,
+}
+
+/// read a spec, `.json` files as JSON and everything else as YAML
+pub fn load_mock_spec(path: &Path) -> Result<MockSpec> {
+    let content = fs::read_to_string(path)?;
+    let spec = if path.extension().is_some_and(|e| e == "json") {
+        serde_json::from_str(&content).map_err(|e| e.to_string())
+    } else {
+        serde_yaml::from_str(&content).map_err(|e| e.to_string())
+    };
+    spec.map_err(|e| ProcessError::BadEncoding(format!("{}: {}", path.display(), e)))
+}
+
+/// the mocked routes, unknown paths get a JSON 404
+pub(super) fn mock_routes(spec: &MockSpec) -> Result<Router> {
+    let mut paths: BTreeMap<&str, MethodRouter> = BTreeMap::new();
+    let mut seen = HashSet::new();
+    for route in &spec.routes {
+        let method = route.method.to_ascii_uppercase();
+        if !seen.insert((route.path.as_str(), method.clone())) {
+            return Err(ProcessError::InvalidConfig(format!(
+                "{} {} is mocked twice",
+                method, route.path
+            )));
+        }
+        let mock = Arc::new(Mock::new(route)?);
+        let handler = move |params: RawPathParams,
+                            Query(query): Query<HashMap<String, String>>,
+                            method: Method,
+                            uri: Uri,
+                            body: Bytes| {
+            let mock = mock.clone();
+            async move { mock.respond(&params, query, &method, &uri, &body).await }
+        };
+        let methods = paths.remove(route.path.as_str()).unwrap_or_default();
+        let methods = if method == "ANY" {
+            // 其他明确写出的方法优先
+            methods.fallback(handler)
+        } else {
+            let filter = Method::from_bytes(method.as_bytes())
+                .ok()
+                .and_then(|m| MethodFilter::try_from(m).ok())
+                .ok_or_else(|| {
+                    ProcessError::InvalidConfig(format!("Unsupported method {}", route.method))
+                })?;
+            methods.on(filter, handler)
+        };
+        paths.insert(&route.path, methods);
+    }
+
+    if let Some(path) = paths.keys().find(|p| !p.starts_with('/')) {
+        return Err(ProcessError::InvalidConfig(format!(
+            "Mock path {:?} must start with /",
+            path
+        )));
+    }
+    // `/users/:id` 和 `/users/:name` 这样的冲突会让 axum panic
+    super::check_routes(paths.keys().copied())?;
+    let mut router = Router::new();
+    for (path, methods) in paths {
+        router = router.route(path, methods);
+    }
+    Ok(router.fallback(|method: Method, uri: Uri| async move {
+        (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({ "error": format!("No mock for {} {}", method, uri.path()) })),
+        )
+    }))
+}
+
+// region:    --- impls
+impl Mock {
+    fn new(route: &MockRoute) -> Result<Self> {
+        let status = StatusCode::from_u16(route.status).map_err(ProcessError::invalid_config)?;
+        let delay = match &route.delay {
+            Some(delay) => Some(
+                fancy_duration::FancyDuration::<Duration>::parse(delay)
+                    .map_err(|_| ProcessError::InvalidConfig(format!("Invalid delay {:?}", delay)))?
+                    .0,
+            ),
+            None => None,
+        };
+        let mut headers = HeaderMap::new();
+        let body = match (&route.json, &route.yaml, &route.body) {
+            (None, None, None) => MockBody::Empty,
+            (Some(json), None, None) => {
+                headers.insert(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("application/json"),
+                );
+                MockBody::Json(json.clone())
+            }
+            (None, Some(yaml), None) => {
+                headers.insert(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("application/yaml"),
+                );
+                MockBody::Yaml(yaml.clone())
+            }
+            (None, None, Some(_)) => {
+                headers.insert(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("text/plain; charset=utf-8"),
+                );
+                MockBody::Text
+            }
+            _ => {
+                return Err(ProcessError::InvalidConfig(format!(
+                    "{} {}: only one of json, yaml and body",
+                    route.method, route.path
+                )))
+            }
+        };
+        for (name, value) in &route.headers {
+            headers.insert(
+                HeaderName::try_from(name).map_err(ProcessError::invalid_config)?,
+                HeaderValue::try_from(value).map_err(ProcessError::invalid_config)?,
+            );
+        }
+
+        // 启动时就检查模板语法
+        let mut tera = Tera::default();
+        let mut templated = HashSet::new();
+        if let Some(body) = &route.body {
+            tera.add_raw_template("body", body)
+                .map_err(ProcessError::invalid_config)?;
+        }
+        if let MockBody::Json(value) | MockBody::Yaml(value) = &body {
+            let mut templates = Vec::new();
+            collect_templates(value, String::new(), &mut templates);
+            for (pointer, template) in templates {
+                tera.add_raw_template(&pointer, template)
+                    .map_err(ProcessError::invalid_config)?;
+                templated.insert(pointer);
+            }
+        }
+        Ok(Self {
+            status,
+            delay,
+            headers,
+            body,
+            tera,
+            templated,
+        })
+    }
+
+    async fn respond(
+        &self,
+        params: &RawPathParams,
+        query: HashMap<String, String>,
+        method: &Method,
+        uri: &Uri,
+        body: &[u8],
+    ) -> Response {
+        if let Some(delay) = self.delay {
+            tokio::time::sleep(delay).await;
+        }
+        let params = params
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<HashMap<_, _>>();
+        let mut context = Context::new();
+        for (k, v) in &params {
+            context.insert(k.as_str(), v);
+        }
+        context.insert("params", &params);
+        context.insert("query", &query);
+        context.insert("method", method.as_str());
+        context.insert("path", uri.path());
+        let body = serde_json::from_slice::<Value>(body)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(body).into_owned()));
+        context.insert("body", &body);
+
+        let rendered = match &self.body {
+            MockBody::Empty => Ok(String::new()),
+            MockBody::Text => self
+                .tera
+                .render("body", &context)
+                .map_err(ProcessError::other),
+            MockBody::Json(value) => self
+                .render_value(value, String::new(), &context)
+                .and_then(|v| Ok(serde_json::to_string_pretty(&v)?)),
+            MockBody::Yaml(value) => self
+                .render_value(value, String::new(), &context)
+                .and_then(|v| serde_yaml::to_string(&v).map_err(ProcessError::other)),
+        };
+        match rendered {
+            Ok(content) => (self.status, self.headers.clone(), content).into_response(),
+            Err(e) => {
+                warn!("Mock template of {} failed: {:?}", uri.path(), e);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Mock template failed: {:?}", e),
+                )
+                    .into_response()
+            }
+        }
+    }
+
+    /// `value` with its templated strings rendered
+    fn render_value(&self, value: &Value, pointer: String, context: &Context) -> Result<Value> {
+        Ok(match value {
+            Value::String(_) if self.templated.contains(&pointer) => Value::String(
+                self.tera
+                    .render(&pointer, context)
+                    .map_err(ProcessError::other)?,
+            ),
+            Value::Array(items) => Value::Array(
+                items
+                    .iter()
+                    .enumerate()
+                    .map(|(i, v)| self.render_value(v, format!("{}/{}", pointer, i), context))
+                    .collect::<Result<_>>()?,
+            ),
+            Value::Object(map) => Value::Object(
+                map.iter()
+                    .map(|(k, v)| {
+                        let child = format!("{}/{}", pointer, escape_pointer(k));
+                        Ok((k.clone(), self.render_value(v, child, context)?))
+                    })
+                    .collect::<Result<_>>()?,
+            ),
+            v => v.clone(),
+        })
+    }
+}
+// endregion: --- impls
+
+/// (JSON pointer, template) of the strings that use tera syntax
+fn collect_templates<'a>(value: &'a Value, pointer: String, out: &mut Vec<(String, &'a str)>) {
+    match value {
+        Value::String(s) if s.contains("{{") || s.contains("{%") => out.push((pointer, s)),
+        Value::Array(items) => {
+            for (i, v) in items.iter().enumerate() {
+                collect_templates(v, format!("{}/{}", pointer, i), out);
+            }
+        }
+        Value::Object(map) => {
+            for (k, v) in map {
+                collect_templates(v, format!("{}/{}", pointer, escape_pointer(k)), out);
+            }
+        }
+        _ => {}
+    }
+}
+
+// RFC 6901
+fn escape_pointer(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+fn default_status() -> u16 {
+    200
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_templates() {
+        let spec: MockSpec = serde_yaml::from_str(
+            r#"
+            routes:
+              - path: /users/:id
+                json:
+                  id: "{{ id }}"
+                  tags: ["a", "{{ query.tag }}"]
+                  a/b: "{{ method }}"
+                  fixed: 1
+            "#,
+        )
+        .unwrap();
+        let mock = Mock::new(&spec.routes[0]).unwrap();
+        let mut templated = mock.templated.iter().cloned().collect::<Vec<_>>();
+        templated.sort();
+        assert_eq!(templated, ["/a~1b", "/id", "/tags/1"]);
+
+        let mut context = Context::new();
+        context.insert("id", "42");
+        context.insert("method", "GET");
+        context.insert("query", &HashMap::from([("tag", "x")]));
+        let MockBody::Json(value) = &mock.body else {
+            panic!("expected a JSON body");
+        };
+        let rendered = mock.render_value(value, String::new(), &context).unwrap();
+        assert_eq!(
+            rendered,
+            serde_json::json!({ "id": "42", "tags": ["a", "x"], "a/b": "GET", "fixed": 1 })
+        );
+
+        let both = MockRoute {
+            json: Some(Value::Null),
+            body: Some(String::new()),
+            ..spec.routes[0].clone()
+        };
+        assert!(Mock::new(&both).is_err());
+
+        // axum 会 panic 的路由在启动时报错
+        let conflict: MockSpec = serde_yaml::from_str(
+            r#"
+            routes:
+              - path: /users/:id
+              - path: /users/:name
+            "#,
+        )
+        .unwrap();
+        assert!(mock_routes(&conflict).is_err());
+        let catch_all = MockSpec {
+            routes: vec![MockRoute {
+                path: "/files/*rest/x".to_string(),
+                ..spec.routes[0].clone()
+            }],
+        };
+        assert!(mock_routes(&catch_all).is_err());
+    }
+}
@@ -0,0 +1,242 @@
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::any,
+    Router,
+};
+use reqwest::{redirect::Policy, Client, Url};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+use sync_wrapper::SyncStream;
+use tracing::{debug, warn};
+
+use crate::process::error::{ProcessError, Result};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+// 只对一跳连接有意义, 不能转发 (RFC 9110 7.6.1)
+const HOP_BY_HOP: [&str; 8] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// forward requests below `mount` to an upstream server
+#[derive(Debug, Clone)]
+pub struct HttpProxy {
+    /// url prefix, `/` for everything
+    pub mount: String,
+    /// `http://127.0.0.1:3000`, a path in it is put in front of the forwarded path
+    pub upstream: String,
+    /// drop `mount` from the forwarded path, `/api/users` becomes `/users`
+    pub strip_prefix: bool,
+}
+
+#[derive(Debug)]
+struct Upstream {
+    client: Client,
+    url: Url,
+    mount: String,
+    strip_prefix: bool,
+    /// the `Authorization` header was meant for `http serve --auth`, not the upstream
+    strip_authorization: bool,
+}
+
+/// `<mount>` and `<mount>/*rest` for any method. `mount` is normalized, without a trailing `/`
+pub(super) fn proxy_routes(
+    proxy: &HttpProxy,
+    mount: &str,
+    strip_authorization: bool,
+) -> Result<Router> {
+    let url = Url::parse(&proxy.upstream).map_err(ProcessError::invalid_config)?;
+    if !matches!(url.scheme(), "http" | "https") || url.host().is_none() {
+        return Err(ProcessError::InvalidConfig(format!(
+            "Upstream must be an http(s) URL: {}",
+            proxy.upstream
+        )));
+    }
+    let client = Client::builder()
+        .redirect(Policy::none())
+        .connect_timeout(CONNECT_TIMEOUT)
+        // 本地开发的上游不走系统代理
+        .no_proxy()
+        .build()
+        .map_err(ProcessError::other)?;
+    let upstream = Arc::new(Upstream {
+        client,
+        url,
+        mount: mount.to_string(),
+        strip_prefix: proxy.strip_prefix,
+        strip_authorization,
+    });
+    let router = route_paths(mount)
+        .iter()
+        .fold(Router::new(), |router, path| {
+            router.route(path, any(forward))
+        });
+    Ok(router.with_state(upstream))
+}
+
+/// `<mount>/`, `<mount>/*rest` and `<mount>` itself when it isn't the root
+pub(super) fn route_paths(mount: &str) -> Vec<String> {
+    let mut paths = vec![format!("{}/", mount), format!("{}/*rest", mount)];
+    if !mount.is_empty() {
+        paths.push(mount.to_string());
+    }
+    paths
+}
+
+/// stream the request to the upstream and its response back, 502 when it can't be reached
+async fn forward(
+    State(upstream): State<Arc<Upstream>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    request: Request,
+) -> Response {
+    let (parts, body) = request.into_parts();
+    let url = upstream.url_for(parts.uri.path(), parts.uri.query());
+    debug!("Proxying {} {} to {}", parts.method, parts.uri, url);
+
+    let mut headers = strip_hop_by_hop(&parts.headers);
+    // reqwest 按上游地址设置 Host
+    headers.remove(header::HOST);
+    if upstream.strip_authorization {
+        headers.remove(header::AUTHORIZATION);
+    }
+    if let Some(ConnectInfo(addr)) = connect_info {
+        let forwarded_for = match parts
+            .headers
+            .get("x-forwarded-for")
+            .and_then(|v| v.to_str().ok())
+        {
+            Some(previous) => format!("{}, {}", previous, addr.ip()),
+            None => addr.ip().to_string(),
+        };
+        if let Ok(value) = HeaderValue::from_str(&forwarded_for) {
+            headers.insert(HeaderName::from_static("x-forwarded-for"), value);
+        }
+    }
+    if let Some(host) = parts.headers.get(header::HOST) {
+        headers.insert(HeaderName::from_static("x-forwarded-host"), host.clone());
+    }
+
+    let mut builder = upstream
+        .client
+        .request(parts.method, url.clone())
+        .headers(headers);
+    // 没有 Content-Length 也没有分块的请求没有 body, 不要让 reqwest 改成分块发送
+    if parts.headers.contains_key(header::CONTENT_LENGTH)
+        || parts.headers.contains_key(header::TRANSFER_ENCODING)
+    {
+        // reqwest 要求 Sync 的流
+        let stream = SyncStream::new(body.into_data_stream());
+        builder = builder.body(reqwest::Body::wrap_stream(stream));
+    }
+    match builder.send().await {
+        Ok(response) => {
+            let status = response.status();
+            let headers = strip_hop_by_hop(response.headers());
+            let mut proxied = Response::new(Body::from_stream(response.bytes_stream()));
+            *proxied.status_mut() = status;
+            *proxied.headers_mut() = headers;
+            proxied
+        }
+        Err(e) if e.is_timeout() => {
+            warn!("Upstream {} timed out", url);
+            (StatusCode::GATEWAY_TIMEOUT, "Upstream timed out").into_response()
+        }
+        Err(e) => {
+            warn!("Upstream {} failed: {}", url, e);
+            (
+                StatusCode::BAD_GATEWAY,
+                format!("Upstream unavailable: {}", e),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// without hop-by-hop headers and those listed in `Connection`
+fn strip_hop_by_hop(headers: &HeaderMap) -> HeaderMap {
+    let listed = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|name| name.trim().to_ascii_lowercase())
+        .collect::<Vec<_>>();
+    let mut out = HeaderMap::with_capacity(headers.len());
+    for (name, value) in headers {
+        let name_str = name.as_str();
+        if HOP_BY_HOP.contains(&name_str) || listed.iter().any(|l| l == name_str) {
+            continue;
+        }
+        out.append(name, value.clone());
+    }
+    out
+}
+
+// region:    --- impls
+impl Upstream {
+    /// the upstream URL of a request path (percent-encoded) and query
+    fn url_for(&self, path: &str, query: Option<&str>) -> Url {
+        let rest = if self.strip_prefix {
+            path.strip_prefix(&self.mount).unwrap_or(path)
+        } else {
+            path
+        };
+        let base = self.url.path().trim_end_matches('/');
+        let mut url = self.url.clone();
+        match rest {
+            "" => url.set_path(&format!("{}/", base)),
+            rest if rest.starts_with('/') => url.set_path(&format!("{}{}", base, rest)),
+            rest => url.set_path(&format!("{}/{}", base, rest)),
+        }
+        url.set_query(query);
+        url
+    }
+}
+// endregion: --- impls
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proxy_url_and_headers() {
+        let upstream = |url: &str, strip_prefix| Upstream {
+            client: Client::new(),
+            url: Url::parse(url).unwrap(),
+            mount: "/api".to_string(),
+            strip_prefix,
+            strip_authorization: false,
+        };
+        let plain = upstream("http://127.0.0.1:3000", false);
+        assert_eq!(
+            plain.url_for("/api/users", Some("page=2")).as_str(),
+            "http://127.0.0.1:3000/api/users?page=2"
+        );
+        let stripped = upstream("http://127.0.0.1:3000/v1/", true);
+        assert_eq!(
+            stripped.url_for("/api/users/a%20b", None).as_str(),
+            "http://127.0.0.1:3000/v1/users/a%20b"
+        );
+        assert_eq!(
+            stripped.url_for("/api", None).as_str(),
+            "http://127.0.0.1:3000/v1/"
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONNECTION, "keep-alive, x-secret".parse().unwrap());
+        headers.insert("x-secret", "1".parse().unwrap());
+        headers.insert(header::TRANSFER_ENCODING, "chunked".parse().unwrap());
+        headers.insert(header::ACCEPT, "application/json".parse().unwrap());
+        let stripped = strip_hop_by_hop(&headers);
+        assert_eq!(stripped.len(), 1);
+        assert!(stripped.contains_key(header::ACCEPT));
+    }
+}
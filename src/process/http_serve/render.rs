@@ -0,0 +1,209 @@
+use axum::{
+    http::{header, HeaderMap, HeaderValue},
+    response::{Html, IntoResponse, Response},
+};
+use pulldown_cmark::{html, CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+use std::{fs, path::Path};
+use syntect::{
+    highlighting::{Theme, ThemeSet},
+    html::highlighted_html_for_string,
+    parsing::{SyntaxReference, SyntaxSet},
+};
+use tera::{Context, Tera};
+
+use crate::process::{
+    csv_convert::read_csv,
+    error::{ProcessError, Result},
+};
+
+const TEMPLATE_NAME: &str = "render.html";
+const LAYOUT: &str = include_str!("render.html");
+const THEME: &str = "InspiredGitHub";
+// 高亮很慢, 更大的文件直接发送原文
+const MAX_RENDER_SIZE: u64 = 1024 * 1024;
+
+/// how a file is shown by `--render`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum RenderKind {
+    Markdown,
+    Csv,
+    Source,
+}
+
+/// `--render`: markdown, CSV and source files as HTML pages
+#[derive(Debug)]
+pub(super) struct Renderer {
+    tera: Tera,
+    syntaxes: SyntaxSet,
+    theme: Theme,
+}
+
+impl Renderer {
+    pub(super) fn new() -> Result<Self> {
+        let mut tera = Tera::default();
+        tera.add_raw_template(TEMPLATE_NAME, LAYOUT)
+            .map_err(ProcessError::other)?;
+        let theme = ThemeSet::load_defaults()
+            .themes
+            .remove(THEME)
+            .ok_or_else(|| ProcessError::UnsupportedFormat(format!("syntax theme {}", THEME)))?;
+        Ok(Self {
+            tera,
+            syntaxes: SyntaxSet::load_defaults_newlines(),
+            theme,
+        })
+    }
+
+    /// `None` when the file is sent as is: unknown or plain text types, HTML the
+    /// browser shows by itself, and files too large to highlight
+    pub(super) fn kind(&self, path: &Path) -> Option<RenderKind> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        if fs::metadata(path).ok()?.len() > MAX_RENDER_SIZE {
+            return None;
+        }
+        match ext.as_str() {
+            "md" | "markdown" => Some(RenderKind::Markdown),
+            "csv" => Some(RenderKind::Csv),
+            "html" | "htm" | "xhtml" | "svg" | "txt" => None,
+            _ => self
+                .syntaxes
+                .find_syntax_by_extension(&ext)
+                .map(|_| RenderKind::Source),
+        }
+    }
+
+    /// the page for `path`, titled with its file name
+    pub(super) fn render(&self, path: &Path, kind: RenderKind) -> Result<Response> {
+        let title = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut context = Context::new();
+        context.insert("title", &title);
+        context.insert("kind", kind.as_str());
+        match kind {
+            RenderKind::Markdown => {
+                let text = String::from_utf8_lossy(&fs::read(path)?).into_owned();
+                context.insert("content", &self.markdown(&text));
+            }
+            RenderKind::Csv => {
+                let (headers, records) = read_csv(path)?;
+                let rows = records
+                    .iter()
+                    .map(|r| r.iter().collect::<Vec<_>>())
+                    .collect::<Vec<_>>();
+                context.insert("headers", &headers.iter().collect::<Vec<_>>());
+                context.insert("rows", &rows);
+            }
+            RenderKind::Source => {
+                let text = String::from_utf8_lossy(&fs::read(path)?).into_owned();
+                let syntax = path
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .and_then(|e| self.syntaxes.find_syntax_by_extension(e))
+                    .unwrap_or_else(|| self.syntaxes.find_syntax_plain_text());
+                context.insert("content", &self.highlight(&text, syntax)?);
+            }
+        }
+        let page = self
+            .tera
+            .render(TEMPLATE_NAME, &context)
+            .map_err(ProcessError::other)?;
+        let mut response = Html(page).into_response();
+        // 同一个 URL, `curl` 和 `<script src>` 拿到的是原文
+        response
+            .headers_mut()
+            .insert(header::VARY, HeaderValue::from_static("accept"));
+        Ok(response)
+    }
+
+    /// GFM tables, task lists and strikethrough, fenced code highlighted like source files
+    fn markdown(&self, text: &str) -> String {
+        let options = Options::ENABLE_TABLES
+            | Options::ENABLE_STRIKETHROUGH
+            | Options::ENABLE_TASKLISTS
+            | Options::ENABLE_FOOTNOTES;
+        let mut events = Vec::new();
+        // (语言, 代码) of the fenced block being read
+        let mut code: Option<(String, String)> = None;
+        for event in Parser::new_ext(text, options) {
+            match (event, code.as_mut()) {
+                (Event::Start(Tag::CodeBlock(block)), _) => {
+                    let lang = match block {
+                        CodeBlockKind::Fenced(info) => {
+                            info.split_whitespace().next().unwrap_or("").to_string()
+                        }
+                        CodeBlockKind::Indented => String::new(),
+                    };
+                    code = Some((lang, String::new()));
+                }
+                (Event::Text(t), Some((_, buf))) => buf.push_str(&t),
+                (Event::End(TagEnd::CodeBlock), _) => {
+                    if let Some((lang, buf)) = code.take() {
+                        let syntax = self
+                            .syntaxes
+                            .find_syntax_by_token(&lang)
+                            .unwrap_or_else(|| self.syntaxes.find_syntax_plain_text());
+                        // 高亮失败时保留 pulldown-cmark 的普通代码块
+                        match self.highlight(&buf, syntax) {
+                            Ok(html) => events.push(Event::Html(html.into())),
+                            Err(_) => {
+                                events.push(Event::Start(Tag::CodeBlock(CodeBlockKind::Indented)));
+                                events.push(Event::Text(buf.into()));
+                                events.push(Event::End(TagEnd::CodeBlock));
+                            }
+                        }
+                    }
+                }
+                (event, _) => events.push(event),
+            }
+        }
+        let mut out = String::with_capacity(text.len() * 3 / 2);
+        html::push_html(&mut out, events.into_iter());
+        out
+    }
+
+    fn highlight(&self, text: &str, syntax: &SyntaxReference) -> Result<String> {
+        highlighted_html_for_string(text, &self.syntaxes, syntax, &self.theme)
+            .map_err(ProcessError::other)
+    }
+}
+
+/// browsers ask for `text/html` when navigating, not for scripts, styles or `fetch`
+pub(super) fn wants_html(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("text/html"))
+}
+
+// region:    --- impls
+impl RenderKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            RenderKind::Markdown => "markdown",
+            RenderKind::Csv => "csv",
+            RenderKind::Source => "source",
+        }
+    }
+}
+// endregion: --- impls
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_markdown() {
+        let renderer = Renderer::new().unwrap();
+        let html = renderer
+            .markdown("# Title\n\n| a | b |\n|---|---|\n| 1 | 2 |\n\n```rust\nfn main() {}\n```\n");
+        assert!(html.contains("<h1>Title</h1>"));
+        assert!(html.contains("<table>"));
+        assert!(html.contains("<td>2</td>"));
+        // 高亮后的代码块带内联样式
+        assert!(html.contains("<pre style="));
+        assert!(html.contains("main"));
+        assert!(!html.contains("<code class=\"language-rust\">"));
+    }
+}
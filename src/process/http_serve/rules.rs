@@ -0,0 +1,416 @@
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+    response::{IntoResponse, Response},
+    Router,
+};
+use globset::{GlobBuilder, GlobMatcher};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tower::ServiceExt;
+use tracing::{debug, warn};
+
+use super::render;
+use crate::process::error::{ProcessError, Result};
+
+/// routing rules of `http serve --config serve.toml`. Paths are globs matched against the
+/// request path as sent (percent-encoded), `*` stays within one segment, `**` spans several
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServeRules {
+    /// `[[redirect]]`, the first match wins
+    #[serde(rename = "redirect")]
+    pub redirects: Vec<RedirectRule>,
+    /// `[[rewrite]]`: serve another path under the requested one, the first match wins
+    #[serde(rename = "rewrite")]
+    pub rewrites: Vec<RewriteRule>,
+    /// `[[headers]]`, every match applies, later ones override
+    pub headers: Vec<HeaderRule>,
+    /// `[[not_found]]`: pages sent with 404, the first match wins
+    pub not_found: Vec<NotFoundRule>,
+    /// `[[mount]]`: more directories under url prefixes
+    #[serde(rename = "mount")]
+    pub mounts: Vec<Mount>,
+}
+
+/// `to` may be a path or a full URL. With `from = "/old/**"`, `{path}` in `to`
+/// is what follows `/old/`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RedirectRule {
+    pub from: String,
+    pub to: String,
+    /// 301, 302, 303, 307 or 308
+    #[serde(default = "default_redirect_status")]
+    pub status: u16,
+}
+
+/// `to` is a path below the root, `{path}` works as for redirects
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RewriteRule {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HeaderRule {
+    pub path: String,
+    /// header name to value, e.g. `{ "Cache-Control" = "max-age=3600" }`
+    pub set: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NotFoundRule {
+    #[serde(default = "default_not_found_path")]
+    pub path: String,
+    /// url path of the page, e.g. `/404.html`
+    pub page: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Mount {
+    /// e.g. `/assets`
+    pub prefix: String,
+    /// relative to the config file
+    pub dir: PathBuf,
+}
+
+/// glob of a rule, plus the literal prefix of `/prefix/**` whose rest fills `{path}`
+#[derive(Debug)]
+struct Pattern {
+    glob: GlobMatcher,
+    prefix: Option<String>,
+}
+
+#[derive(Debug)]
+struct Redirect {
+    from: Pattern,
+    to: String,
+    status: StatusCode,
+}
+
+#[derive(Debug)]
+struct Rewrite {
+    from: Pattern,
+    to: String,
+}
+
+/// rules checked on every request, mounts are part of the inner router
+#[derive(Debug, Default)]
+pub(super) struct CompiledRules {
+    redirects: Vec<Redirect>,
+    rewrites: Vec<Rewrite>,
+    headers: Vec<(GlobMatcher, HeaderMap)>,
+    not_found: Vec<(GlobMatcher, String)>,
+    /// unknown paths get `/index.html`
+    spa: bool,
+}
+
+/// state of the outer router: the file routes and the rules around them
+#[derive(Debug, Clone)]
+pub(super) struct RulesState {
+    pub(super) inner: Router,
+    pub(super) rules: Arc<CompiledRules>,
+}
+
+/// read a `serve.toml`, mount directories are resolved relative to it
+pub fn load_serve_rules(path: &Path) -> Result<ServeRules> {
+    let content = fs::read_to_string(path)?;
+    let mut rules: ServeRules = toml::from_str(&content)
+        .map_err(|e| ProcessError::BadEncoding(format!("{}: {}", path.display(), e)))?;
+    let base = path.parent().unwrap_or(Path::new(""));
+    for mount in &mut rules.mounts {
+        mount.dir = base.join(&mount.dir);
+    }
+    Ok(rules)
+}
+
+/// fallback of the outer router: redirects and rewrites, then the file routes,
+/// then the SPA fallback, 404 pages and custom headers
+pub(super) async fn apply_rules(State(state): State<RulesState>, mut request: Request) -> Response {
+    let rules = &state.rules;
+    let path = request.uri().path().to_string();
+    let query = request.uri().query().map(str::to_string);
+
+    if let Some((to, status)) = rules
+        .redirects
+        .iter()
+        .find_map(|r| r.from.apply(&path, &r.to).map(|to| (to, r.status)))
+    {
+        let location = with_query(to, query.as_deref());
+        return match HeaderValue::from_str(&location) {
+            Ok(location) => (status, [(header::LOCATION, location)]).into_response(),
+            Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        };
+    }
+    if let Some(to) = rules
+        .rewrites
+        .iter()
+        .find_map(|r| r.from.apply(&path, &r.to))
+    {
+        match with_query(to, query.as_deref()).parse() {
+            Ok(uri) => {
+                debug!("Rewrote {} to {}", path, uri);
+                *request.uri_mut() = uri;
+            }
+            Err(e) => warn!("Invalid rewrite of {}: {}", path, e),
+        }
+    }
+
+    let method = request.method().clone();
+    let headers = request.headers().clone();
+    let mut response = call(&state.inner, request).await;
+    if response.status() == StatusCode::NOT_FOUND && matches!(method, Method::GET | Method::HEAD) {
+        let page = if rules.spa && spa_fallback(&path, &headers) {
+            Some(("/index.html", StatusCode::OK))
+        } else {
+            rules
+                .not_found
+                .iter()
+                .find(|(glob, _)| glob.is_match(&path))
+                .map(|(_, page)| (page.as_str(), StatusCode::NOT_FOUND))
+        };
+        if let Some((page, status)) = page {
+            let fallback = fetch(&state.inner, page, &method, &headers).await;
+            // 页面本身不存在时保留原来的 404
+            if fallback.status() == StatusCode::OK {
+                response = fallback;
+                *response.status_mut() = status;
+            }
+        }
+    }
+    for (glob, set) in &rules.headers {
+        if glob.is_match(&path) {
+            for (name, value) in set {
+                response.headers_mut().insert(name, value.clone());
+            }
+        }
+    }
+    response
+}
+
+// region:    --- impls
+impl CompiledRules {
+    pub(super) fn new(rules: &ServeRules, spa: bool) -> Result<Self> {
+        let redirects = rules
+            .redirects
+            .iter()
+            .map(|r| {
+                let status = StatusCode::from_u16(r.status)
+                    .ok()
+                    .filter(StatusCode::is_redirection)
+                    .ok_or_else(|| {
+                        ProcessError::InvalidConfig(format!(
+                            "{} is not a redirect status",
+                            r.status
+                        ))
+                    })?;
+                Ok(Redirect {
+                    from: Pattern::new(&r.from)?,
+                    to: r.to.clone(),
+                    status,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let rewrites = rules
+            .rewrites
+            .iter()
+            .map(|r| {
+                if !r.to.starts_with('/') {
+                    return Err(ProcessError::InvalidConfig(format!(
+                        "rewrite target {:?} must be a path",
+                        r.to
+                    )));
+                }
+                Ok(Rewrite {
+                    from: Pattern::new(&r.from)?,
+                    to: r.to.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let headers = rules
+            .headers
+            .iter()
+            .map(|r| {
+                let mut set = HeaderMap::new();
+                for (name, value) in &r.set {
+                    set.insert(
+                        HeaderName::try_from(name).map_err(ProcessError::invalid_config)?,
+                        HeaderValue::try_from(value).map_err(ProcessError::invalid_config)?,
+                    );
+                }
+                Ok((Pattern::new(&r.path)?.glob, set))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let not_found = rules
+            .not_found
+            .iter()
+            .map(|r| Ok((Pattern::new(&r.path)?.glob, r.page.clone())))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            redirects,
+            rewrites,
+            headers,
+            not_found,
+            spa,
+        })
+    }
+
+    /// whether there is anything to do around the file routes
+    pub(super) fn is_empty(&self) -> bool {
+        !self.spa
+            && self.redirects.is_empty()
+            && self.rewrites.is_empty()
+            && self.headers.is_empty()
+            && self.not_found.is_empty()
+    }
+}
+
+impl Pattern {
+    fn new(pattern: &str) -> Result<Self> {
+        if !pattern.starts_with('/') {
+            return Err(ProcessError::InvalidConfig(format!(
+                "path pattern {:?} must start with /",
+                pattern
+            )));
+        }
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(ProcessError::invalid_config)?
+            .compile_matcher();
+        let prefix = pattern
+            .strip_suffix("/**")
+            .filter(|p| !p.contains(['*', '?', '[', '{']))
+            .map(str::to_string);
+        Ok(Self { glob, prefix })
+    }
+
+    /// `to` with `{path}` filled in, if `path` matches
+    fn apply(&self, path: &str, to: &str) -> Option<String> {
+        if !self.glob.is_match(path) {
+            return None;
+        }
+        let rest = self
+            .prefix
+            .as_deref()
+            .and_then(|prefix| path.strip_prefix(prefix))
+            .map_or("", |rest| rest.trim_start_matches('/'));
+        Some(to.replace("{path}", rest))
+    }
+}
+// endregion: --- impls
+
+async fn call(router: &Router, request: Request) -> Response {
+    match router.clone().oneshot(request).await {
+        Ok(response) => response,
+        Err(e) => match e {},
+    }
+}
+
+/// `GET` another path with the client's headers, without the conditional and range ones
+async fn fetch(router: &Router, path: &str, method: &Method, headers: &HeaderMap) -> Response {
+    let mut request = Request::new(Body::empty());
+    *request.method_mut() = method.clone();
+    *request.uri_mut() = match path.parse() {
+        Ok(uri) => uri,
+        Err(_) => return StatusCode::NOT_FOUND.into_response(),
+    };
+    let skip = [
+        header::RANGE,
+        header::IF_RANGE,
+        header::IF_NONE_MATCH,
+        header::IF_MODIFIED_SINCE,
+    ];
+    for (name, value) in headers {
+        if !skip.contains(name) {
+            request.headers_mut().append(name, value.clone());
+        }
+    }
+    call(router, request).await
+}
+
+// 前端路由看起来不像文件; 浏览器导航也总是要页面
+fn spa_fallback(path: &str, headers: &HeaderMap) -> bool {
+    let last = path.rsplit('/').next().unwrap_or("");
+    !last.contains('.') || render::wants_html(headers)
+}
+
+fn with_query(to: String, query: Option<&str>) -> String {
+    match query {
+        Some(query) if !to.contains('?') => format!("{}?{}", to, query),
+        _ => to,
+    }
+}
+
+fn default_redirect_status() -> u16 {
+    301
+}
+
+fn default_not_found_path() -> String {
+    "/**".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serve_rules() {
+        let rules: ServeRules = toml::from_str(
+            r#"
+            [[redirect]]
+            from = "/old/**"
+            to = "/new/{path}"
+
+            [[headers]]
+            path = "/assets/*.js"
+            set = { "Cache-Control" = "max-age=3600" }
+
+            [[not_found]]
+            page = "/404.html"
+            "#,
+        )
+        .unwrap();
+        let compiled = CompiledRules::new(&rules, false).unwrap();
+        assert!(!compiled.is_empty());
+        let redirect = &compiled.redirects[0];
+        assert_eq!(redirect.status, StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(
+            redirect
+                .from
+                .apply("/old/a/b.html", &redirect.to)
+                .as_deref(),
+            Some("/new/a/b.html")
+        );
+        assert_eq!(redirect.from.apply("/older/a", &redirect.to), None);
+        // `*` 不跨目录
+        assert!(compiled.headers[0].0.is_match("/assets/app.js"));
+        assert!(!compiled.headers[0].0.is_match("/assets/vendor/app.js"));
+        assert!(compiled.not_found[0].0.is_match("/any/where"));
+
+        let bad = ServeRules {
+            redirects: vec![RedirectRule {
+                from: "/a".to_string(),
+                to: "/b".to_string(),
+                status: 200,
+            }],
+            ..Default::default()
+        };
+        assert!(CompiledRules::new(&bad, false).is_err());
+        assert!(toml::from_str::<ServeRules>(
+            "[[redirect]]\nfrom = \"/a\"\nto = \"/b\"\nbogus = 1"
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,371 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{Multipart, Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Redirect, Response},
+};
+use futures::{Stream, StreamExt};
+use std::{
+    fmt, io,
+    path::{Component, Path as FsPath, PathBuf},
+    sync::Arc,
+};
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+
+use super::{listing, resolve_path, HttpServeState, HttpUpload};
+use crate::OverwritePolicy;
+
+// 同名文件最多尝试 `name (1)` .. `name (999)`
+const MAX_RENAME_ATTEMPTS: usize = 1000;
+
+/// an upload that could not be stored, with the status sent to the client
+#[derive(Debug)]
+struct UploadError(StatusCode, String);
+
+/// `PUT /<path>`: the raw body becomes the file, 201 when created, 204 when replaced
+pub(super) async fn put_handler(
+    State(state): State<Arc<HttpServeState>>,
+    Path(subpath): Path<String>,
+    headers: HeaderMap,
+    body: Body,
+) -> Response {
+    let Some(upload) = &state.upload else {
+        return StatusCode::METHOD_NOT_ALLOWED.into_response();
+    };
+    let result = async {
+        // 有 Content-Length 时提前拒绝, 不用先收完
+        let length = headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+        if length.is_some_and(|len| len > upload.max_size) {
+            return Err(too_large(upload));
+        }
+        let (dir, name) = put_target(&state, &subpath)?;
+        save(&dir, &name, body.into_data_stream(), upload).await
+    }
+    .await;
+    match result {
+        Ok((path, false)) => {
+            info!("Uploaded {:?}", path);
+            StatusCode::CREATED.into_response()
+        }
+        Ok((path, true)) => {
+            info!("Replaced {:?}", path);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => e.into_response(),
+    }
+}
+
+/// `POST /`: multipart form of the listing page
+pub(super) async fn root_form_handler(
+    State(state): State<Arc<HttpServeState>>,
+    headers: HeaderMap,
+    multipart: Multipart,
+) -> Response {
+    form_upload(&state, "", &headers, multipart).await
+}
+
+/// `POST /<dir>/`: multipart form of the listing page
+pub(super) async fn form_handler(
+    State(state): State<Arc<HttpServeState>>,
+    Path(subpath): Path<String>,
+    headers: HeaderMap,
+    multipart: Multipart,
+) -> Response {
+    form_upload(&state, &subpath, &headers, multipart).await
+}
+
+async fn form_upload(
+    state: &HttpServeState,
+    subpath: &str,
+    headers: &HeaderMap,
+    mut multipart: Multipart,
+) -> Response {
+    let Some(upload) = &state.upload else {
+        return StatusCode::METHOD_NOT_ALLOWED.into_response();
+    };
+    // 表单 POST 不需要预检, 其他网站的页面也能提交, 浏览器还会带上缓存的 Basic 认证
+    if is_cross_site(headers) {
+        warn!("Refused a cross-site upload to {:?}", subpath);
+        return UploadError(
+            StatusCode::FORBIDDEN,
+            "Cross-site uploads are not allowed, use PUT".to_string(),
+        )
+        .into_response();
+    }
+    let dir = match resolve_path(state, subpath) {
+        Ok(dir) if dir.is_dir() => dir,
+        Ok(_) => return StatusCode::METHOD_NOT_ALLOWED.into_response(),
+        Err(status) => return status.into_response(),
+    };
+
+    let mut uploaded = Vec::new();
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => return UploadError(e.status(), e.body_text()).into_response(),
+        };
+        // 没有文件名的是普通表单字段
+        let Some(file_name) = field.file_name().map(str::to_string) else {
+            continue;
+        };
+        // 浏览器可能带上客户端的路径, 只保留文件名
+        let Some(name) = sanitize_file_name(&file_name) else {
+            return UploadError(
+                StatusCode::BAD_REQUEST,
+                format!("Invalid file name {:?}", file_name),
+            )
+            .into_response();
+        };
+        match save(&dir, &name, field, upload).await {
+            Ok((path, _)) => {
+                info!("Uploaded {:?}", path);
+                let saved = path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or(name);
+                uploaded.push(saved);
+            }
+            Err(e) => return e.into_response(),
+        }
+    }
+
+    let base = listing::url_path(
+        &format!("{}/{}", state.mount, subpath)
+            .split('/')
+            .filter(|s| !s.is_empty() && *s != ".")
+            .collect::<Vec<_>>(),
+    );
+    if listing::wants_json(headers) {
+        Json(serde_json::json!({ "path": base, "uploaded": uploaded })).into_response()
+    } else {
+        // 回到目录页面
+        Redirect::to(&base).into_response()
+    }
+}
+
+/// parent directory and file name of a `PUT` path
+fn put_target(state: &HttpServeState, subpath: &str) -> Result<(PathBuf, String), UploadError> {
+    let path = FsPath::new(subpath);
+    let name = match path.components().next_back() {
+        Some(Component::Normal(name)) if !subpath.ends_with('/') => {
+            name.to_string_lossy().into_owned()
+        }
+        _ => {
+            return Err(UploadError(
+                StatusCode::BAD_REQUEST,
+                "PUT needs a file path".to_string(),
+            ))
+        }
+    };
+    let parent = path
+        .parent()
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let dir = resolve_path(state, &parent)
+        .map_err(|status| UploadError(status, format!("Directory {:?} not available", parent)))?;
+    if !dir.is_dir() {
+        return Err(UploadError(
+            StatusCode::CONFLICT,
+            format!("{:?} is not a directory", parent),
+        ));
+    }
+    // 已存在的目标也要做一遍路径检查, 比如不允许的符号链接
+    let target = dir.join(&name);
+    if fs_exists(&target) {
+        match resolve_path(state, subpath) {
+            Ok(existing) if existing.is_dir() => {
+                return Err(UploadError(
+                    StatusCode::CONFLICT,
+                    format!("{:?} is a directory", subpath),
+                ))
+            }
+            Ok(_) => {}
+            Err(status) => return Err(UploadError(status, "Forbidden".to_string())),
+        }
+    }
+    Ok((dir, name))
+}
+
+/// stream into a temp file next to the target, then move it into place.
+/// Returns the final path and whether an existing file was replaced
+async fn save<S, E>(
+    dir: &FsPath,
+    name: &str,
+    mut stream: S,
+    upload: &HttpUpload,
+) -> Result<(PathBuf, bool), UploadError>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: fmt::Display,
+{
+    let tmp = dir.join(format!(
+        ".{}.rcli-upload-{:016x}",
+        name,
+        rand::random::<u64>()
+    ));
+    let result = async {
+        let mut file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp)
+            .await?;
+        let mut written = 0u64;
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| UploadError(StatusCode::BAD_REQUEST, e.to_string()))?;
+            written += chunk.len() as u64;
+            if written > upload.max_size {
+                return Err(too_large(upload));
+            }
+            file.write_all(&chunk).await?;
+        }
+        file.sync_all().await?;
+        drop(file);
+        commit(&tmp, dir, name, upload.overwrite)
+    }
+    .await;
+    // 成功时临时文件已经被移走或者只剩一个多余的硬链接
+    if let Err(e) = tokio::fs::remove_file(&tmp).await {
+        if e.kind() != io::ErrorKind::NotFound {
+            warn!("Failed to remove {:?}: {}", tmp, e);
+        }
+    }
+    result
+}
+
+// rename 会覆盖已有文件, hard_link 不会, 用它做不覆盖的原子写入
+fn commit(
+    tmp: &FsPath,
+    dir: &FsPath,
+    name: &str,
+    overwrite: OverwritePolicy,
+) -> Result<(PathBuf, bool), UploadError> {
+    let target = dir.join(name);
+    match overwrite {
+        OverwritePolicy::Replace => {
+            let existed = fs_exists(&target);
+            std::fs::rename(tmp, &target)?;
+            Ok((target, existed))
+        }
+        OverwritePolicy::Deny => match std::fs::hard_link(tmp, &target) {
+            Ok(()) => Ok((target, false)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Err(UploadError(
+                StatusCode::CONFLICT,
+                format!("{} already exists", name),
+            )),
+            Err(e) => Err(e.into()),
+        },
+        OverwritePolicy::Rename => {
+            for i in 0..MAX_RENAME_ATTEMPTS {
+                let candidate = if i == 0 {
+                    target.clone()
+                } else {
+                    dir.join(numbered_name(name, i))
+                };
+                match std::fs::hard_link(tmp, &candidate) {
+                    Ok(()) => return Ok((candidate, false)),
+                    Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            Err(UploadError(
+                StatusCode::CONFLICT,
+                format!("Too many files named like {}", name),
+            ))
+        }
+    }
+}
+
+/// `report (1).pdf` for `report.pdf`
+fn numbered_name(name: &str, i: usize) -> String {
+    match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => format!("{} ({}).{}", stem, i, ext),
+        _ => format!("{} ({})", name, i),
+    }
+}
+
+fn sanitize_file_name(name: &str) -> Option<String> {
+    // windows 浏览器可能发送 `C:\fakepath\a.txt`
+    let name = name.rsplit(['/', '\\']).next()?.trim();
+    match FsPath::new(name).components().next() {
+        Some(Component::Normal(_)) if !name.contains('\0') => Some(name.to_string()),
+        _ => None,
+    }
+}
+
+/// the request comes from a page of another origin. Requests without `Sec-Fetch-Site`
+/// and `Origin` (curl, scripts) are not from a browser page and allowed
+fn is_cross_site(headers: &HeaderMap) -> bool {
+    // same-site 也包括同一主机的其他端口
+    if let Some(site) = headers.get("sec-fetch-site") {
+        return !matches!(site.to_str(), Ok("same-origin" | "none"));
+    }
+    let Some(origin) = headers.get(header::ORIGIN) else {
+        return false;
+    };
+    let origin_host = origin
+        .to_str()
+        .ok()
+        .and_then(|o| o.split_once("://"))
+        .map(|(_, host)| host);
+    let host = headers.get(header::HOST).and_then(|h| h.to_str().ok());
+    // `Origin: null` 来自沙箱和本地文件
+    match (origin_host, host) {
+        (Some(origin_host), Some(host)) => !origin_host.eq_ignore_ascii_case(host),
+        _ => true,
+    }
+}
+
+// 符号链接本身存在就算存在
+fn fs_exists(path: &FsPath) -> bool {
+    std::fs::symlink_metadata(path).is_ok()
+}
+
+fn too_large(upload: &HttpUpload) -> UploadError {
+    UploadError(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        format!("Upload exceeds the limit of {} bytes", upload.max_size),
+    )
+}
+
+// region:    --- impls
+impl From<io::Error> for UploadError {
+    fn from(e: io::Error) -> Self {
+        warn!("Upload failed: {}", e);
+        UploadError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    }
+}
+
+impl IntoResponse for UploadError {
+    fn into_response(self) -> Response {
+        (self.0, self.1).into_response()
+    }
+}
+// endregion: --- impls
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upload_file_names() {
+        assert_eq!(numbered_name("report.pdf", 1), "report (1).pdf");
+        assert_eq!(numbered_name("archive.tar.gz", 2), "archive.tar (2).gz");
+        assert_eq!(numbered_name("README", 3), "README (3)");
+        assert_eq!(numbered_name(".env", 1), ".env (1)");
+
+        assert_eq!(sanitize_file_name("a.txt"), Some("a.txt".to_string()));
+        assert_eq!(
+            sanitize_file_name("C:\\fakepath\\a.txt"),
+            Some("a.txt".to_string())
+        );
+        assert_eq!(sanitize_file_name("../../a.txt"), Some("a.txt".to_string()));
+        assert_eq!(sanitize_file_name(".."), None);
+        assert_eq!(sanitize_file_name("dir/"), None);
+        assert_eq!(sanitize_file_name(""), None);
+    }
+}
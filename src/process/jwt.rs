@@ -1,7 +1,8 @@
-use anyhow::Result;
 use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
 
+use super::error::{ProcessError, Result};
+
 /// 创建Claims
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
@@ -12,11 +13,11 @@ pub struct Claims {
 }
 
 /// 签名Jwt
-pub fn process_jwt_sign(key: &str, sub: &str, aud: &str, exp: usize) -> Result<String> {
+pub fn process_jwt_sign(key: &[u8], sub: &str, aud: &str, exp: usize) -> Result<String> {
     // 创建header 这里暂时用HS256算法
     let header = Header::new(Algorithm::HS256);
     // 创建EncodingKey
-    let encoding_key = EncodingKey::from_secret(key.as_bytes());
+    let encoding_key = EncodingKey::from_secret(key);
 
     // issued at: Token 签发时间
     let iat = chrono::Utc::now().timestamp() as usize;
@@ -34,21 +35,36 @@ pub fn process_jwt_sign(key: &str, sub: &str, aud: &str, exp: usize) -> Result<S
     tracing::info!("Claims: {:?}", claims);
 
     // 生成token
-    let token = jsonwebtoken::encode(&header, &claims, &encoding_key)?;
+    let token =
+        jsonwebtoken::encode(&header, &claims, &encoding_key).map_err(ProcessError::other)?;
 
     Ok(token)
 }
 
 /// 验证Jwt
-pub fn process_jwt_verify(key: &str, token: &str) -> Result<bool> {
+pub fn process_jwt_verify(key: &[u8], token: &str) -> Result<bool> {
+    // 要设置验证过期时间 但是不验证目标
+    process_jwt_validate(key, token, None, None)
+}
+
+/// 验证Jwt, 并且要求 `aud` / `sub` 和给定的值一致 (给了的话)
+pub fn process_jwt_validate(
+    key: &[u8],
+    token: &str,
+    aud: Option<&str>,
+    sub: Option<&str>,
+) -> Result<bool> {
     // 创建DecodingKey
-    let decoding_key = DecodingKey::from_secret(key.as_bytes());
+    let decoding_key = DecodingKey::from_secret(key);
 
     // 创建验证器
     let mut validation = Validation::new(Algorithm::HS256);
-    // 要设置验证过期时间 但是不验证目标
-    validation.validate_aud = false;
     validation.validate_exp = true;
+    match aud {
+        Some(aud) => validation.set_audience(&[aud]),
+        None => validation.validate_aud = false,
+    }
+    validation.sub = sub.map(str::to_string);
 
     let result = jsonwebtoken::decode::<Claims>(token, &decoding_key, &validation);
     // 这里只要结果是否正常
@@ -61,7 +77,7 @@ mod tests {
 
     #[test]
     fn test_jwt_sign_verify() -> Result<()> {
-        let key = "testKey";
+        let key = b"testKey";
         let sub = "testSub";
         let aud = "testAud";
         // 60 秒过期
@@ -71,6 +87,11 @@ mod tests {
 
         assert!(process_jwt_verify(key, &token)?);
 
+        assert!(process_jwt_validate(key, &token, Some(aud), Some(sub))?);
+        assert!(!process_jwt_validate(key, &token, Some("otherAud"), None)?);
+        assert!(!process_jwt_validate(key, &token, None, Some("otherSub"))?);
+        assert!(!process_jwt_validate(b"otherKey", &token, None, None)?);
+
         Ok(())
     }
 }
@@ -0,0 +1,170 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rand::{rngs::OsRng, RngCore};
+
+use super::error::{ProcessError, Result};
+use crate::{is_sealed_key, KeyEncoding};
+
+/// context used by `text derive` when `--context` is not given
+pub const DEFAULT_DERIVE_CONTEXT: &str = "rcli 2024-06-01 text derive blake3 key";
+const HEX_PREFIX: &str = "hex:";
+const BASE64_PREFIX: &str = "base64:";
+// argon2 要求 salt 至少 8 个字节
+const MIN_SALT_LEN: usize = 8;
+// 参数固定下来, 不跟随 argon2 crate 的默认值变化, 否则同样的 passphrase 会得到不同的 key
+const ARGON2_M_COST: u32 = 19 * 1024;
+const ARGON2_T_COST: u32 = 2;
+const ARGON2_P_COST: u32 = 1;
+
+/// derive a 32 byte key from a passphrase: argon2id(passphrase, salt), then
+/// `blake3::derive_key(context, ..)` so different contexts give independent keys
+pub fn process_key_derive(passphrase: &str, salt: &[u8], context: &str) -> Result<[u8; 32]> {
+    if salt.len() < MIN_SALT_LEN {
+        return Err(ProcessError::InvalidKey(format!(
+            "salt must be at least {} bytes",
+            MIN_SALT_LEN
+        )));
+    }
+    let params = Params::new(ARGON2_M_COST, ARGON2_T_COST, ARGON2_P_COST, Some(32))
+        .map_err(ProcessError::bad_encoding)?;
+    let mut stretched = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut stretched)
+        .map_err(|e| ProcessError::InvalidKey(format!("failed to derive key: {}", e)))?;
+    Ok(process_subkey_derive(&stretched, context))
+}
+
+/// independent subkey of a full-entropy key, e.g. one per purpose or per session
+pub fn process_subkey_derive(key: &[u8; 32], context: &str) -> [u8; 32] {
+    blake3::derive_key(context, key)
+}
+
+/// 32 random bytes from the OS
+pub fn generate_symmetric_key() -> [u8; 32] {
+    let mut key = [0u8; 32];
+    OsRng.fill_bytes(&mut key);
+    key
+}
+
+/// key file content with its encoding spelled out: `hex:<hex>` or `base64:<base64>`
+pub fn encode_symmetric_key(key: &[u8; 32], encoding: KeyEncoding) -> String {
+    match encoding {
+        KeyEncoding::Hex => format!("{}{}\n", HEX_PREFIX, hex::encode(key)),
+        KeyEncoding::Base64 => format!("{}{}\n", BASE64_PREFIX, STANDARD.encode(key)),
+    }
+}
+
+/// 32 byte key of a blake3 key file: `hex:`/`base64:` encoded, or an older file
+/// (raw bytes or printable text) of which the first 32 bytes are the key
+pub fn load_symmetric_key(data: &[u8]) -> Result<[u8; 32]> {
+    if let Some(key) = decode_symmetric_key(data) {
+        return key;
+    }
+    reject_sealed_key(data)?;
+    // 旧版本的 key 文件不会是 JSON, 例如 keyring 的 entry 或签名文件
+    if data.trim_ascii_start().starts_with(b"{") {
+        return Err(ProcessError::InvalidKey(
+            "expected a blake3 key file, got JSON".into(),
+        ));
+    }
+    // 旧版本生成的 key 文件是 base64 文本, 一直只使用前 32 个字节
+    data.get(..32)
+        .and_then(|k| k.try_into().ok())
+        .ok_or(ProcessError::InvalidKeyLength {
+            expected: 32,
+            actual: data.len(),
+        })
+}
+
+// 加密的 key 文件不能当作 key 本身使用, 否则会静默得到一个错误的 key
+pub(crate) fn reject_sealed_key(data: &[u8]) -> Result<()> {
+    if is_sealed_key(data) {
+        return Err(ProcessError::InvalidKey(
+            "key file is encrypted, unseal it with --passphrase-file".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// decode a key written by `encode_symmetric_key`. Returns `None` for files without the
+/// prefix, so older key files keep their meaning
+pub fn decode_symmetric_key(data: &[u8]) -> Option<Result<[u8; 32]>> {
+    Some(decode_prefixed_key(data)?.and_then(|key| {
+        let len = key.len();
+        key.try_into().map_err(|_| ProcessError::InvalidKeyLength {
+            expected: 32,
+            actual: len,
+        })
+    }))
+}
+
+/// the bytes of a `hex:` / `base64:` key file of any length, `None` without a prefix
+pub(crate) fn decode_prefixed_key(data: &[u8]) -> Option<Result<Vec<u8>>> {
+    let text = std::str::from_utf8(data).ok()?.trim();
+    if let Some(key) = text.strip_prefix(HEX_PREFIX) {
+        Some(hex::decode(key).map_err(ProcessError::from))
+    } else {
+        let key = text.strip_prefix(BASE64_PREFIX)?;
+        Some(STANDARD.decode(key).map_err(ProcessError::from))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_derive() -> Result<()> {
+        let key = process_key_derive("correct horse", b"rcli-salt", DEFAULT_DERIVE_CONTEXT)?;
+        assert_eq!(
+            key,
+            process_key_derive("correct horse", b"rcli-salt", DEFAULT_DERIVE_CONTEXT)?
+        );
+        assert_ne!(
+            key,
+            process_key_derive("correct horse", b"rcli-salt2", DEFAULT_DERIVE_CONTEXT)?
+        );
+        assert_ne!(
+            key,
+            process_key_derive("correct horse", b"rcli-salt", "other")?
+        );
+        assert!(process_key_derive("correct horse", b"short", DEFAULT_DERIVE_CONTEXT).is_err());
+
+        let a = process_subkey_derive(&key, "rcli test a");
+        assert_ne!(a, process_subkey_derive(&key, "rcli test b"));
+        assert_eq!(a, blake3::derive_key("rcli test a", &key));
+        Ok(())
+    }
+
+    #[test]
+    fn test_symmetric_key_encoding() -> Result<()> {
+        let key = generate_symmetric_key();
+        for encoding in [KeyEncoding::Hex, KeyEncoding::Base64] {
+            let encoded = encode_symmetric_key(&key, encoding);
+            assert_eq!(decode_symmetric_key(encoded.as_bytes()).unwrap()?, key);
+        }
+        assert!(decode_symmetric_key(include_bytes!("../../fixtures/blake3.txt")).is_none());
+        assert!(matches!(
+            decode_symmetric_key(b"hex:abcd"),
+            Some(Err(ProcessError::InvalidKeyLength { actual: 2, .. }))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_symmetric_key_rejects_sealed_and_json() -> Result<()> {
+        let key = include_bytes!("../../fixtures/blake3.txt");
+        assert_eq!(load_symmetric_key(key)?, key[..32]);
+        let sealed = crate::seal_key(key, "correct horse")?;
+        assert!(matches!(
+            load_symmetric_key(&sealed),
+            Err(ProcessError::InvalidKey(_))
+        ));
+        let json = br#"{"algorithm": "blake3", "signature": "0123456789abcdef0123456789abcdef"}"#;
+        assert!(matches!(
+            load_symmetric_key(json),
+            Err(ProcessError::InvalidKey(_))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,383 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    error::{ProcessError, Result},
+    text::{text_signer, text_verifier},
+};
+use crate::{get_content, is_sealed_key, open_key, write_key_file, TextSignFormat};
+
+const KEYRING_ENV: &str = "RCLI_KEYRING";
+const KEY_EXTENSION: &str = "json";
+// 指纹前缀至少 4 个字符, 避免误匹配
+const MIN_FINGERPRINT_PREFIX: usize = 4;
+
+/// Named keys under `~/.config/rcli/keyring`, one json file per key
+#[derive(Debug, Clone)]
+pub struct Keyring {
+    dir: PathBuf,
+}
+
+/// a keyring entry, key files are stored as-is (base64), encrypted private keys stay encrypted
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyEntry {
+    pub name: String,
+    pub algorithm: String,
+    /// same as the `key_id` in signature files
+    pub fingerprint: String,
+    pub created_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub private: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyKind {
+    Private,
+    Public,
+}
+
+impl KeyEntry {
+    pub fn format(&self) -> Result<TextSignFormat> {
+        self.algorithm
+            .parse()
+            .map_err(|_| ProcessError::UnsupportedFormat(self.algorithm.clone()))
+    }
+
+    pub fn private_key(&self) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .private
+            .as_ref()
+            .map(|k| STANDARD.decode(k))
+            .transpose()?)
+    }
+
+    pub fn public_key(&self) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .public
+            .as_ref()
+            .map(|k| STANDARD.decode(k))
+            .transpose()?)
+    }
+
+    /// key material for signing (private) or verifying (public, or the shared key of
+    /// symmetric algorithms)
+    pub fn key(&self, kind: KeyKind) -> Result<Vec<u8>> {
+        let key = match kind {
+            KeyKind::Private => self.private_key()?,
+            KeyKind::Public if self.format()?.is_symmetric() => self.private_key()?,
+            KeyKind::Public => self.public_key()?,
+        };
+        let kind = match kind {
+            KeyKind::Private => "private",
+            KeyKind::Public => "public",
+        };
+        key.ok_or_else(|| ProcessError::KeyNotFound(format!("{} has no {} key", self.name, kind)))
+    }
+}
+
+impl Keyring {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// `RCLI_KEYRING`, otherwise `$XDG_CONFIG_HOME/rcli/keyring` or `~/.config/rcli/keyring`
+    pub fn open_default() -> Result<Self> {
+        if let Ok(dir) = std::env::var(KEYRING_ENV) {
+            return Ok(Self::new(dir));
+        }
+        let config = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) => PathBuf::from(dir),
+            None => std::env::var_os("HOME")
+                .or_else(|| std::env::var_os("USERPROFILE"))
+                .map(|home| Path::new(&home).join(".config"))
+                .ok_or_else(|| {
+                    ProcessError::InvalidConfig(
+                        "cannot find the home directory, set XDG_CONFIG_HOME".into(),
+                    )
+                })?,
+        };
+        Ok(Self::new(config.join("rcli").join("keyring")))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn list(&self) -> Result<Vec<KeyEntry>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut entries = Vec::new();
+        for file in fs::read_dir(&self.dir)? {
+            let path = file?.path();
+            if path.extension().and_then(|e| e.to_str()) == Some(KEY_EXTENSION) {
+                entries.push(serde_json::from_slice::<KeyEntry>(&fs::read(&path)?)?);
+            }
+        }
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(entries)
+    }
+
+    /// find a key by name, full fingerprint or an unambiguous fingerprint prefix
+    pub fn find(&self, query: &str) -> Result<Option<KeyEntry>> {
+        let entries = self.list()?;
+        if let Some(entry) = entries.iter().find(|e| e.name == query) {
+            return Ok(Some(entry.clone()));
+        }
+        if query.len() < MIN_FINGERPRINT_PREFIX {
+            return Ok(None);
+        }
+        let query = query.to_ascii_lowercase();
+        let mut matched = entries
+            .into_iter()
+            .filter(|e| e.fingerprint.starts_with(&query));
+        match (matched.next(), matched.next()) {
+            (Some(entry), None) => Ok(Some(entry)),
+            (Some(_), Some(_)) => Err(ProcessError::InvalidKey(format!(
+                "fingerprint {} matches more than one key",
+                query
+            ))),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn get(&self, query: &str) -> Result<KeyEntry> {
+        self.find(query)?.ok_or_else(|| {
+            ProcessError::KeyNotFound(format!("{} in {}", query, self.dir.display()))
+        })
+    }
+
+    pub fn add(&self, entry: &KeyEntry, force: bool) -> Result<()> {
+        verify_key_name(&entry.name)?;
+        let path = self.path(&entry.name);
+        if path.exists() && !force {
+            return Err(ProcessError::Io(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!(
+                    "key {} already exists, use --force to replace it",
+                    entry.name
+                ),
+            )));
+        }
+        fs::create_dir_all(&self.dir)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&self.dir, fs::Permissions::from_mode(0o700))?;
+        }
+        write_key_file(path, &serde_json::to_vec_pretty(entry)?).map_err(ProcessError::other)
+    }
+
+    pub fn delete(&self, query: &str) -> Result<KeyEntry> {
+        let entry = self.get(query)?;
+        fs::remove_file(self.path(&entry.name))?;
+        Ok(entry)
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", name, KEY_EXTENSION))
+    }
+}
+
+/// fingerprint of a key, private keys must already be decrypted
+pub fn key_fingerprint(key: &[u8], format: TextSignFormat, kind: KeyKind) -> Result<String> {
+    let id = match kind {
+        KeyKind::Private => text_signer(key, format)?.key_id(),
+        KeyKind::Public => text_verifier(key, format)?.key_id(),
+    };
+    Ok(id)
+}
+
+/// build a keyring entry from key file contents, at least one of them is required.
+/// `passphrase` is only asked for an encrypted private key
+pub fn process_key_import(
+    name: &str,
+    format: TextSignFormat,
+    private: Option<Vec<u8>>,
+    public: Option<Vec<u8>>,
+    passphrase: impl FnOnce() -> Result<String>,
+) -> Result<KeyEntry> {
+    verify_key_name(name)?;
+    let private_fingerprint = match &private {
+        Some(private) if is_sealed_key(private) => {
+            let private = open_key(private, &passphrase()?)?;
+            Some(key_fingerprint(&private, format, KeyKind::Private)?)
+        }
+        Some(private) => Some(key_fingerprint(private, format, KeyKind::Private)?),
+        None => None,
+    };
+    let fingerprint = match (private_fingerprint, &public) {
+        // 两个 key 都给了时必须是同一对, 否则签名和验证会用到不相关的 key
+        (Some(fingerprint), Some(public)) if !format.is_symmetric() => {
+            if key_fingerprint(public, format, KeyKind::Public)? != fingerprint {
+                return Err(ProcessError::InvalidKey(
+                    "private and public keys are not a pair".into(),
+                ));
+            }
+            fingerprint
+        }
+        (Some(fingerprint), _) => fingerprint,
+        (None, Some(public)) if !format.is_symmetric() => {
+            key_fingerprint(public, format, KeyKind::Public)?
+        }
+        (None, Some(_)) => {
+            return Err(ProcessError::InvalidKey(format!(
+                "{} is symmetric, import it with --private",
+                format
+            )))
+        }
+        (None, None) => {
+            return Err(ProcessError::KeyNotFound(
+                "a private or public key is required".into(),
+            ))
+        }
+    };
+    Ok(KeyEntry {
+        name: name.to_string(),
+        algorithm: format.to_string(),
+        fingerprint,
+        created_at: chrono::Utc::now().to_rfc3339(),
+        private: private.map(|k| STANDARD.encode(k)),
+        public: public.map(|k| STANDARD.encode(k)),
+    })
+}
+
+/// `--key` accepts a file path, a keyring name or a fingerprint.
+/// Returns the key file content and, for keyring keys, their algorithm
+pub fn load_key(key: &str, kind: KeyKind) -> Result<(Vec<u8>, Option<TextSignFormat>)> {
+    if key == "-" || Path::new(key).exists() {
+        return Ok((get_content(key).map_err(ProcessError::other)?, None));
+    }
+    let entry = Keyring::open_default()?.find(key)?.ok_or_else(|| {
+        ProcessError::KeyNotFound(format!("{} is neither a key file nor a keyring key", key))
+    })?;
+    Ok((entry.key(kind)?, Some(entry.format()?)))
+}
+
+// name 会作为文件名, 不能包含路径分隔符
+fn verify_key_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '@'));
+    if !valid {
+        return Err(ProcessError::InvalidKey(format!(
+            "invalid key name: {}",
+            name
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{process_text_key_generate, seal_key, KeyFormat};
+
+    const KEY: &[u8] = include_bytes!("../../fixtures/blake3.txt");
+
+    fn no_passphrase() -> Result<String> {
+        Err(ProcessError::other(anyhow::anyhow!(
+            "passphrase should not be asked"
+        )))
+    }
+
+    // fixtures 中的 ed25519 key 按旧格式读取时不是一对
+    fn ed25519_pair() -> Result<(Vec<u8>, Vec<u8>)> {
+        let mut keys = process_text_key_generate(TextSignFormat::Ed25519, KeyFormat::Raw, None)?;
+        Ok((
+            keys.remove("ed25519.sk").unwrap(),
+            keys.remove("ed25519.pk").unwrap(),
+        ))
+    }
+
+    #[test]
+    fn test_keyring_add_find_delete() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("rcli-keyring-{}", std::process::id()));
+        let keyring = Keyring::new(&dir);
+        let (sk, pk) = ed25519_pair()?;
+        let alice = process_key_import(
+            "alice",
+            TextSignFormat::Ed25519,
+            Some(sk),
+            Some(pk.clone()),
+            no_passphrase,
+        )?;
+        keyring.add(&alice, false)?;
+        let shared = process_key_import(
+            "shared",
+            TextSignFormat::Blake3,
+            Some(KEY.to_vec()),
+            None,
+            no_passphrase,
+        )?;
+        keyring.add(&shared, false)?;
+        assert!(keyring.add(&shared, false).is_err());
+
+        assert_eq!(keyring.list()?, vec![alice.clone(), shared.clone()]);
+        assert_eq!(keyring.get("alice")?, alice);
+        assert_eq!(keyring.get(&alice.fingerprint[..6])?, alice);
+        assert_eq!(keyring.get("alice")?.key(KeyKind::Public)?, pk);
+        // 对称 key 的 public 就是它自己
+        assert_eq!(keyring.get("shared")?.key(KeyKind::Public)?, KEY);
+        assert!(matches!(
+            keyring.get("bob"),
+            Err(ProcessError::KeyNotFound(_))
+        ));
+
+        keyring.delete("alice")?;
+        let names = keyring
+            .list()?
+            .into_iter()
+            .map(|e| e.name)
+            .collect::<Vec<_>>();
+        fs::remove_dir_all(&dir)?;
+        assert_eq!(names, vec!["shared"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_import_fingerprint() -> Result<()> {
+        // 只有私钥时, 指纹和用公钥导入的一致
+        let (sk, pk) = ed25519_pair()?;
+        let public =
+            process_key_import("a", TextSignFormat::Ed25519, None, Some(pk), no_passphrase)?;
+        let sealed = seal_key(&sk, "correct horse")?;
+        let private = process_key_import("a", TextSignFormat::Ed25519, Some(sealed), None, || {
+            Ok("correct horse".to_string())
+        })?;
+        assert_eq!(public.fingerprint, private.fingerprint);
+        assert!(private.key(KeyKind::Public).is_err());
+
+        // 不相关的私钥和公钥不能作为一对导入
+        let (_, other_pk) = ed25519_pair()?;
+        let ret = process_key_import(
+            "a",
+            TextSignFormat::Ed25519,
+            Some(sk),
+            Some(other_pk),
+            no_passphrase,
+        );
+        assert!(matches!(ret, Err(ProcessError::InvalidKey(_))));
+
+        for name in ["", "../x", ".hidden", "a/b"] {
+            let ret = process_key_import(
+                name,
+                TextSignFormat::Blake3,
+                Some(KEY.to_vec()),
+                None,
+                no_passphrase,
+            );
+            assert!(ret.is_err());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,198 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use super::error::{ProcessError, Result};
+
+const SEALED_KEY_VERSION: u8 = 1;
+const KDF_ARGON2ID: &str = "argon2id";
+const CIPHER_XCHACHA20POLY1305: &str = "xchacha20poly1305";
+const SALT_LEN: usize = 16;
+// 参数来自 key 文件, 限制上限避免恶意文件耗尽内存或 CPU
+const MAX_M_COST: u32 = 1024 * 1024; // KiB, 1 GiB
+const MAX_T_COST: u32 = 10;
+const MAX_P_COST: u32 = 16;
+
+/// Passphrase protected private key, written by `text generate --encrypt`
+///
+/// argon2id derives the key encryption key, XChaCha20-Poly1305 seals the private key.
+/// Everything except the ciphertext is authenticated as associated data.
+#[derive(Debug, Serialize, Deserialize)]
+struct SealedKey {
+    version: u8,
+    kdf: KdfParams,
+    cipher: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct KdfParams {
+    algorithm: String,
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+    salt: String,
+}
+
+impl KdfParams {
+    fn generate() -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        // argon2 crate 的默认参数 (OWASP 推荐): 19 MiB, 2 iterations, 1 lane
+        Self {
+            algorithm: KDF_ARGON2ID.to_string(),
+            m_cost: Params::DEFAULT_M_COST,
+            t_cost: Params::DEFAULT_T_COST,
+            p_cost: Params::DEFAULT_P_COST,
+            salt: STANDARD.encode(salt),
+        }
+    }
+
+    fn derive(&self, passphrase: &str) -> Result<XChaCha20Poly1305> {
+        if self.algorithm != KDF_ARGON2ID {
+            return Err(ProcessError::UnsupportedFormat(format!(
+                "key derivation function {}",
+                self.algorithm
+            )));
+        }
+        if self.m_cost > MAX_M_COST || self.t_cost > MAX_T_COST || self.p_cost > MAX_P_COST {
+            return Err(ProcessError::InvalidKey(format!(
+                "argon2 parameters too large: m_cost {} KiB, t_cost {}, p_cost {}",
+                self.m_cost, self.t_cost, self.p_cost
+            )));
+        }
+        let params = Params::new(self.m_cost, self.t_cost, self.p_cost, Some(32))
+            .map_err(ProcessError::bad_encoding)?;
+        let salt = STANDARD.decode(&self.salt)?;
+        let mut key = [0u8; 32];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+            .map_err(|e| ProcessError::InvalidKey(format!("failed to derive key: {}", e)))?;
+        Ok(XChaCha20Poly1305::new(&key.into()))
+    }
+}
+
+impl SealedKey {
+    fn aad(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(&(
+            self.version,
+            &self.kdf,
+            &self.cipher,
+        ))?)
+    }
+}
+
+pub fn seal_key(key: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    let kdf = KdfParams::generate();
+    let cipher = kdf.derive(passphrase)?;
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let mut sealed = SealedKey {
+        version: SEALED_KEY_VERSION,
+        kdf,
+        cipher: CIPHER_XCHACHA20POLY1305.to_string(),
+        nonce: STANDARD.encode(nonce),
+        ciphertext: String::new(),
+    };
+    let aad = sealed.aad()?;
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: key,
+                aad: &aad,
+            },
+        )
+        .map_err(|_| ProcessError::InvalidKey("key too large to encrypt".into()))?;
+    sealed.ciphertext = STANDARD.encode(ciphertext);
+    Ok(serde_json::to_vec_pretty(&sealed)?)
+}
+
+pub fn open_key(data: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    let sealed: SealedKey = serde_json::from_slice(data)?;
+    if sealed.version != SEALED_KEY_VERSION {
+        return Err(ProcessError::UnsupportedFormat(format!(
+            "encrypted key version {}",
+            sealed.version
+        )));
+    }
+    if sealed.cipher != CIPHER_XCHACHA20POLY1305 {
+        return Err(ProcessError::UnsupportedFormat(sealed.cipher));
+    }
+    let cipher = sealed.kdf.derive(passphrase)?;
+    let nonce = STANDARD.decode(&sealed.nonce)?;
+    if nonce.len() != 24 {
+        return Err(ProcessError::bad_encoding(format!(
+            "invalid nonce length {}",
+            nonce.len()
+        )));
+    }
+    let ciphertext = STANDARD.decode(&sealed.ciphertext)?;
+    let aad = sealed.aad()?;
+    cipher
+        .decrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: &ciphertext,
+                aad: &aad,
+            },
+        )
+        .map_err(|_| {
+            ProcessError::DecryptionFailed("wrong passphrase or corrupted key file".into())
+        })
+}
+
+/// whether the key file was written by `seal_key`
+pub fn is_sealed_key(data: &[u8]) -> bool {
+    serde_json::from_slice::<SealedKey>(data).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &[u8] = include_bytes!("../../fixtures/blake3.txt");
+
+    #[test]
+    fn test_seal_open_key() -> Result<()> {
+        let sealed = seal_key(KEY, "correct horse")?;
+        assert!(is_sealed_key(&sealed));
+        assert!(!is_sealed_key(KEY));
+        assert_eq!(open_key(&sealed, "correct horse")?, KEY);
+        assert!(matches!(
+            open_key(&sealed, "battery staple"),
+            Err(ProcessError::DecryptionFailed(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_tampered_key() -> Result<()> {
+        let sealed = seal_key(KEY, "correct horse")?;
+        let mut tampered: serde_json::Value = serde_json::from_slice(&sealed)?;
+        tampered["kdf"]["t_cost"] = 1.into();
+        let tampered = serde_json::to_vec(&tampered)?;
+        assert!(open_key(&tampered, "correct horse").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_key_with_excessive_params() -> Result<()> {
+        let sealed = seal_key(KEY, "correct horse")?;
+        for (param, value) in [("m_cost", u32::MAX), ("t_cost", 1000), ("p_cost", 1000)] {
+            let mut tampered: serde_json::Value = serde_json::from_slice(&sealed)?;
+            tampered["kdf"][param] = value.into();
+            let tampered = serde_json::to_vec(&tampered)?;
+            assert!(matches!(
+                open_key(&tampered, "correct horse"),
+                Err(ProcessError::InvalidKey(_))
+            ));
+        }
+        Ok(())
+    }
+}
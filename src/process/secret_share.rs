@@ -0,0 +1,203 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use blahaj::{Share, Sharks};
+use rand::{rngs::OsRng, RngCore};
+
+use super::error::{ProcessError, Result};
+use crate::KeyEncoding;
+
+const SHARE_PREFIX: &str = "rcli-share";
+const SHARE_VERSION: u8 = 1;
+// 8 个 hex 字符足够发现抄写错误
+const CHECKSUM_LEN: usize = 8;
+
+/// one share of a split secret, text form:
+/// `rcli-share:1:<threshold>:<secret id>:<hex|base64>:<share>:<checksum>`
+///
+/// The secret id is random per split and tells shares of different splits apart, it
+/// reveals nothing about the secret. The checksum catches typos in a single share.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecretShare {
+    pub threshold: u8,
+    pub secret_id: String,
+    /// x coordinate followed by the share bytes, as produced by `blahaj`
+    pub data: Vec<u8>,
+}
+
+impl SecretShare {
+    /// share number, 1 based
+    pub fn index(&self) -> u8 {
+        self.data[0]
+    }
+
+    pub fn encode(&self, encoding: KeyEncoding) -> String {
+        let data = match encoding {
+            KeyEncoding::Hex => hex::encode(&self.data),
+            KeyEncoding::Base64 => STANDARD.encode(&self.data),
+        };
+        let body = format!(
+            "{}:{}:{}:{}:{}:{}",
+            SHARE_PREFIX, SHARE_VERSION, self.threshold, self.secret_id, encoding, data
+        );
+        let checksum = checksum(&body);
+        format!("{}:{}", body, checksum)
+    }
+
+    pub fn decode(line: &str) -> Result<Self> {
+        let line = line.trim();
+        let (body, checksum_value) = line
+            .rsplit_once(':')
+            .ok_or_else(|| invalid_share("missing checksum"))?;
+        let fields = body.split(':').collect::<Vec<_>>();
+        let [prefix, version, threshold, secret_id, encoding, data] = fields[..] else {
+            return Err(invalid_share("expected 7 fields"));
+        };
+        if prefix != SHARE_PREFIX {
+            return Err(invalid_share("not an rcli share"));
+        }
+        if version != SHARE_VERSION.to_string() {
+            return Err(ProcessError::UnsupportedFormat(format!(
+                "share version {}",
+                version
+            )));
+        }
+        if checksum(body) != checksum_value {
+            return Err(invalid_share(
+                "checksum mismatch, the share was mistyped or modified",
+            ));
+        }
+        let threshold = threshold
+            .parse()
+            .map_err(|_| invalid_share("invalid threshold"))?;
+        let data = match encoding.parse::<KeyEncoding>() {
+            Ok(KeyEncoding::Hex) => hex::decode(data)?,
+            Ok(KeyEncoding::Base64) => STANDARD.decode(data)?,
+            Err(_) => return Err(ProcessError::UnsupportedFormat(encoding.to_string())),
+        };
+        // 至少包含 x 坐标和一个字节
+        if data.len() < 2 || data[0] == 0 {
+            return Err(invalid_share("share data is too short"));
+        }
+        Ok(Self {
+            threshold,
+            secret_id: secret_id.to_string(),
+            data,
+        })
+    }
+}
+
+/// split `secret` into `shares` shares, any `threshold` of them rebuild it
+pub fn process_secret_split(secret: &[u8], threshold: u8, shares: u8) -> Result<Vec<SecretShare>> {
+    if secret.is_empty() {
+        return Err(ProcessError::InvalidKey("secret must not be empty".into()));
+    }
+    if threshold < 2 || threshold > shares {
+        return Err(ProcessError::InvalidConfig(format!(
+            "threshold must be between 2 and the number of shares ({}), got {}",
+            shares, threshold
+        )));
+    }
+    let secret_id = secret_id();
+    Ok(Sharks(threshold)
+        .dealer_rng(secret, &mut OsRng)
+        .take(shares as usize)
+        .map(|share| SecretShare {
+            threshold,
+            secret_id: secret_id.clone(),
+            data: Vec::from(&share),
+        })
+        .collect())
+}
+
+/// rebuild the secret from at least `threshold` shares of the same split
+pub fn process_secret_combine(shares: &[SecretShare]) -> Result<Vec<u8>> {
+    let Some(first) = shares.first() else {
+        return Err(invalid_share("no shares given"));
+    };
+    if shares
+        .iter()
+        .any(|s| s.secret_id != first.secret_id || s.threshold != first.threshold)
+    {
+        return Err(invalid_share("shares belong to different secrets"));
+    }
+    // 同一个 share 给了多次时只保留一个, 重复的 x 坐标会让插值出错
+    let mut shares = shares.to_vec();
+    shares.sort_by_key(|s| s.index());
+    shares.dedup_by_key(|s| s.index());
+    let parsed = shares
+        .iter()
+        .map(|s| Share::try_from(s.data.as_slice()).map_err(invalid_share))
+        .collect::<Result<Vec<_>>>()?;
+    Sharks(first.threshold)
+        .recover(&parsed)
+        .map_err(|e| ProcessError::InvalidKey(format!("{} (need {})", e, first.threshold)))
+}
+
+// 随机的, 哈希会让低熵的秘密可以离线暴力破解
+fn secret_id() -> String {
+    let mut id = [0u8; 4];
+    OsRng.fill_bytes(&mut id);
+    hex::encode(id)
+}
+
+fn checksum(body: &str) -> String {
+    blake3::hash(body.as_bytes()).to_hex()[..CHECKSUM_LEN].to_string()
+}
+
+fn invalid_share(e: impl ToString) -> ProcessError {
+    ProcessError::BadEncoding(format!("invalid share: {}", e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SK: &[u8] = include_bytes!("../../fixtures/ed25519.sk");
+
+    #[test]
+    fn test_secret_split_combine() -> Result<()> {
+        let shares = process_secret_split(SK, 3, 5)?;
+        assert_eq!(shares.len(), 5);
+        assert_eq!(process_secret_combine(&shares[..3])?, SK);
+        assert_eq!(process_secret_combine(&shares[2..])?, SK);
+        assert_eq!(
+            process_secret_combine(&[shares[4].clone(), shares[0].clone(), shares[2].clone()])?,
+            SK
+        );
+        assert!(process_secret_combine(&shares[..2]).is_err());
+        // 同一个 share 重复出现不能凑数
+        let duplicated = [shares[0].clone(), shares[0].clone(), shares[1].clone()];
+        assert!(process_secret_combine(&duplicated).is_err());
+
+        let other = process_secret_split(b"another secret", 3, 5)?;
+        let mixed = [shares[0].clone(), shares[1].clone(), other[2].clone()];
+        assert!(process_secret_combine(&mixed).is_err());
+        // 同一个秘密每次拆分的 id 都不同
+        let again = process_secret_split(SK, 3, 5)?;
+        assert_ne!(again[0].secret_id, shares[0].secret_id);
+        assert!(process_secret_split(SK, 1, 5).is_err());
+        assert!(process_secret_split(SK, 6, 5).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_share_encoding() -> Result<()> {
+        let share = process_secret_split(SK, 2, 3)?.remove(1);
+        for encoding in [KeyEncoding::Hex, KeyEncoding::Base64] {
+            let line = share.encode(encoding);
+            assert!(line.starts_with("rcli-share:1:2:"));
+            assert_eq!(SecretShare::decode(&line)?, share);
+            assert_eq!(share.index(), 2);
+
+            // 改动一个字符, checksum 不再匹配
+            let mut typo = line.into_bytes();
+            let pos = typo.len() - 12;
+            typo[pos] = if typo[pos] == b'a' { b'b' } else { b'a' };
+            let typo = String::from_utf8(typo)?;
+            assert!(matches!(
+                SecretShare::decode(&typo),
+                Err(ProcessError::BadEncoding(_))
+            ));
+        }
+        Ok(())
+    }
+}
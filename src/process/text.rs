@@ -1,9 +1,24 @@
-use crate::{process_genpass, TextSignFormat};
-use anyhow::Result;
-use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use super::error::{ProcessError, Result};
+use super::key_format::{
+    ed25519_file_names, encode_signing_key, encode_verifying_key, load_signing_key,
+    load_verifying_key,
+};
+use super::sealed_key::seal_key;
+use super::sig_algos::{
+    generate_keys, EcdsaP256Signer, EcdsaP256Verifier, EcdsaSecp256k1Signer,
+    EcdsaSecp256k1Verifier, HmacSha256, HmacSha512, RsaPssSigner, RsaPssVerifier,
+};
+use crate::{
+    encode_symmetric_key, generate_symmetric_key, load_symmetric_key, KeyEncoding, KeyFormat,
+    TextSignFormat,
+};
 use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
 use rand::rngs::OsRng;
-use std::{collections::HashMap, io::Read};
+use sha2::{Digest, Sha512};
+use std::{
+    collections::HashMap,
+    io::{self, Read},
+};
 
 pub struct Blake3 {
     key: [u8; 32],
@@ -17,57 +32,116 @@ pub struct Ed25519Verifier {
     key: VerifyingKey,
 }
 
+// Ed25519ph (RFC 8032): 对 SHA-512 摘要签名, 可以流式处理大文件
+// 签名和纯 Ed25519 不兼容, 所以是单独的 TextSignFormat
+pub struct Ed25519phSigner {
+    key: SigningKey,
+}
+
+pub struct Ed25519phVerifier {
+    key: VerifyingKey,
+}
+
+// 写入 signature envelope 的 key id, 用来判断签名是由哪个 key 生成的
+const KEY_ID_CONTEXT: &str = "rcli 2024-06-25 text sign key id";
+
 //  将 sign 和 verify 的行为抽象成 trait
 pub trait TextSigner {
     // signer could sign any input data
     fn sign(&self, reader: &mut dyn Read) -> Result<Vec<u8>>;
+    // id of the key, signer and verifier of the same key pair share it
+    fn key_id(&self) -> String;
 }
 
 pub trait TextVerifier {
     // verifier could verify any input data
     fn verify(&self, reader: &mut dyn Read, sig: &[u8]) -> Result<bool>;
+    fn key_id(&self) -> String;
+}
+
+/// short hex id of a public (or symmetric) key, secret material can't be recovered from it
+pub fn key_id(key: &[u8]) -> String {
+    hex::encode(&blake3::derive_key(KEY_ID_CONTEXT, key)[..8])
 }
 
 impl TextSigner for Blake3 {
     fn sign(&self, reader: &mut dyn Read) -> Result<Vec<u8>> {
-        let mut buf = Vec::new();
-        reader.read_to_end(&mut buf)?;
-        let ret = blake3::keyed_hash(&self.key, &buf);
+        let ret = self.hash(reader)?;
         Ok(ret.as_bytes().to_vec())
     }
+
+    fn key_id(&self) -> String {
+        key_id(&self.key)
+    }
 }
 
 impl TextVerifier for Blake3 {
     fn verify(&self, reader: &mut dyn Read, sig: &[u8]) -> Result<bool> {
-        let mut buf = Vec::new();
-        reader.read_to_end(&mut buf)?;
-        let ret = blake3::keyed_hash(&self.key, &buf);
-        println!("sig: {:?}", sig);
-        println!(
-            "new_sig: {:?}",
-            URL_SAFE_NO_PAD.encode(ret.as_bytes()).as_bytes()
-        );
-        Ok(ret.as_bytes() == sig)
+        let sig = signature_bytes::<32>(sig)?;
+        let ret = self.hash(reader)?;
+        // blake3::Hash 的比较是常量时间的
+        Ok(ret == blake3::Hash::from(sig))
+    }
+
+    fn key_id(&self) -> String {
+        key_id(&self.key)
     }
 }
 
 impl TextSigner for Ed25519Signer {
+    // 纯 Ed25519 需要完整的消息, 大文件请使用 ed25519ph
     fn sign(&self, reader: &mut dyn Read) -> Result<Vec<u8>> {
         let mut buf = Vec::new();
         reader.read_to_end(&mut buf)?;
         let signature = self.key.sign(&buf);
         Ok(signature.to_bytes().to_vec())
     }
+
+    fn key_id(&self) -> String {
+        key_id(self.key.verifying_key().as_bytes())
+    }
 }
 
 impl TextVerifier for Ed25519Verifier {
     fn verify(&self, reader: &mut dyn Read, sig: &[u8]) -> Result<bool> {
+        let signature = Signature::from_bytes(&signature_bytes(sig)?);
         let mut buf = Vec::new();
         reader.read_to_end(&mut buf)?;
-        let sig = (&sig[..64]).try_into()?;
-        let signature = Signature::from_bytes(sig);
         Ok(self.key.verify(&buf, &signature).is_ok())
     }
+
+    fn key_id(&self) -> String {
+        key_id(self.key.as_bytes())
+    }
+}
+
+impl TextSigner for Ed25519phSigner {
+    fn sign(&self, reader: &mut dyn Read) -> Result<Vec<u8>> {
+        let signature = self
+            .key
+            .sign_prehashed(prehash(reader)?, None)
+            .map_err(ProcessError::other)?;
+        Ok(signature.to_bytes().to_vec())
+    }
+
+    fn key_id(&self) -> String {
+        key_id(self.key.verifying_key().as_bytes())
+    }
+}
+
+impl TextVerifier for Ed25519phVerifier {
+    fn verify(&self, reader: &mut dyn Read, sig: &[u8]) -> Result<bool> {
+        let signature = Signature::from_bytes(&signature_bytes(sig)?);
+        let prehashed = prehash(reader)?;
+        Ok(self
+            .key
+            .verify_prehashed(prehashed, None, &signature)
+            .is_ok())
+    }
+
+    fn key_id(&self) -> String {
+        key_id(self.key.as_bytes())
+    }
 }
 
 impl Blake3 {
@@ -78,43 +152,49 @@ impl Blake3 {
     // [u8; N] 对于任何大小的N
     // &[u8]
     pub fn try_new(key: impl AsRef<[u8]>) -> Result<Self> {
-        let key = key.as_ref();
-        // convert &[u8] to &[u8; 32]
-        let key = (&key[..32]).try_into()?;
-        Ok(Self::new(key))
+        Ok(Self::new(load_symmetric_key(key.as_ref())?))
     }
 
     pub fn new(key: [u8; 32]) -> Self {
         Self { key }
     }
 
-    fn generate() -> Result<HashMap<&'static str, Vec<u8>>> {
-        let key = process_genpass(32, true, true, true, true)?;
+    // 增量计算, 内存占用和输入大小无关
+    fn hash(&self, reader: &mut dyn Read) -> Result<blake3::Hash> {
+        let mut hasher = blake3::Hasher::new_keyed(&self.key);
+        io::copy(reader, &mut hasher)?;
+        Ok(hasher.finalize())
+    }
+
+    fn generate(passphrase: Option<&str>) -> Result<HashMap<&'static str, Vec<u8>>> {
+        let key = encode_symmetric_key(&generate_symmetric_key(), KeyEncoding::Base64);
         let mut map = HashMap::new();
-        map.insert("blake3.txt", key.as_bytes().to_vec());
+        map.insert("blake3.txt", seal(key.into_bytes(), passphrase)?);
         Ok(map)
     }
 }
 
 impl Ed25519Signer {
+    // raw, base64, PKCS#8 和 OpenSSH 格式都可以
     pub fn try_new(key: impl AsRef<[u8]>) -> Result<Self> {
-        let key = key.as_ref();
-        let key = (&key[..32]).try_into()?;
-        Ok(Self::new(key))
-    }
-
-    pub fn new(key: &[u8; 32]) -> Self {
-        let key = SigningKey::from_bytes(key);
-        Self { key }
+        let key = load_signing_key(key.as_ref())?;
+        Ok(Self { key })
     }
 
-    fn generate() -> Result<HashMap<&'static str, Vec<u8>>> {
+    fn generate(
+        key_format: KeyFormat,
+        passphrase: Option<&str>,
+    ) -> Result<HashMap<&'static str, Vec<u8>>> {
         let mut csprng = OsRng;
         let sk: SigningKey = SigningKey::generate(&mut csprng);
         let pk: VerifyingKey = (&sk).into();
+        let (sk_name, pk_name) = ed25519_file_names(key_format);
         let mut map = HashMap::new();
-        map.insert("ed25519.sk", sk.to_bytes().to_vec());
-        map.insert("ed25519.pk", pk.to_bytes().to_vec());
+        map.insert(
+            sk_name,
+            seal(encode_signing_key(&sk, key_format)?, passphrase)?,
+        );
+        map.insert(pk_name, encode_verifying_key(&pk, key_format)?);
 
         Ok(map)
     }
@@ -122,24 +202,79 @@ impl Ed25519Signer {
 
 impl Ed25519Verifier {
     pub fn try_new(key: impl AsRef<[u8]>) -> Result<Self> {
-        let key = key.as_ref();
-        let key = (&key[..32]).try_into()?;
-        let key = VerifyingKey::from_bytes(key)?;
+        let key = load_verifying_key(key.as_ref())?;
         Ok(Self { key })
     }
 }
 
-pub fn process_text_sign(
-    reader: &mut dyn Read,
-    key: &[u8], // (ptr, length)
-    format: TextSignFormat,
-) -> Result<Vec<u8>> {
+impl Ed25519phSigner {
+    pub fn try_new(key: impl AsRef<[u8]>) -> Result<Self> {
+        let key = Ed25519Signer::try_new(key)?.key;
+        Ok(Self { key })
+    }
+}
+
+impl Ed25519phVerifier {
+    pub fn try_new(key: impl AsRef<[u8]>) -> Result<Self> {
+        let key = Ed25519Verifier::try_new(key)?.key;
+        Ok(Self { key })
+    }
+}
+
+// 只加密私钥, 公钥保持原样
+pub(crate) fn seal(key: Vec<u8>, passphrase: Option<&str>) -> Result<Vec<u8>> {
+    match passphrase {
+        Some(passphrase) => seal_key(&key, passphrase),
+        None => Ok(key),
+    }
+}
+
+// 长度不对的签名无法解析, 返回错误而不是 panic
+fn signature_bytes<const N: usize>(sig: &[u8]) -> Result<[u8; N]> {
+    sig.try_into()
+        .map_err(|_| ProcessError::BadSignature(format!("expected {} bytes, got {}", N, sig.len())))
+}
+
+fn prehash(reader: &mut dyn Read) -> Result<Sha512> {
+    let mut hasher = Sha512::new();
+    io::copy(reader, &mut hasher)?;
+    Ok(hasher)
+}
+
+pub(crate) fn text_signer(key: &[u8], format: TextSignFormat) -> Result<Box<dyn TextSigner>> {
     let signer: Box<dyn TextSigner> = match format {
         TextSignFormat::Blake3 => Box::new(Blake3::try_new(key)?),
         TextSignFormat::Ed25519 => Box::new(Ed25519Signer::try_new(key)?),
+        TextSignFormat::Ed25519ph => Box::new(Ed25519phSigner::try_new(key)?),
+        TextSignFormat::EcdsaP256 => Box::new(EcdsaP256Signer::try_new(key)?),
+        TextSignFormat::EcdsaSecp256k1 => Box::new(EcdsaSecp256k1Signer::try_new(key)?),
+        TextSignFormat::RsaPss => Box::new(RsaPssSigner::try_new(key)?),
+        TextSignFormat::HmacSha256 => Box::new(HmacSha256::try_new(key)?),
+        TextSignFormat::HmacSha512 => Box::new(HmacSha512::try_new(key)?),
     };
+    Ok(signer)
+}
 
-    signer.sign(reader)
+pub(crate) fn text_verifier(key: &[u8], format: TextSignFormat) -> Result<Box<dyn TextVerifier>> {
+    let verifier: Box<dyn TextVerifier> = match format {
+        TextSignFormat::Blake3 => Box::new(Blake3::try_new(key)?),
+        TextSignFormat::Ed25519 => Box::new(Ed25519Verifier::try_new(key)?),
+        TextSignFormat::Ed25519ph => Box::new(Ed25519phVerifier::try_new(key)?),
+        TextSignFormat::EcdsaP256 => Box::new(EcdsaP256Verifier::try_new(key)?),
+        TextSignFormat::EcdsaSecp256k1 => Box::new(EcdsaSecp256k1Verifier::try_new(key)?),
+        TextSignFormat::RsaPss => Box::new(RsaPssVerifier::try_new(key)?),
+        TextSignFormat::HmacSha256 => Box::new(HmacSha256::try_new(key)?),
+        TextSignFormat::HmacSha512 => Box::new(HmacSha512::try_new(key)?),
+    };
+    Ok(verifier)
+}
+
+pub fn process_text_sign(
+    reader: &mut dyn Read,
+    key: &[u8], // (ptr, length)
+    format: TextSignFormat,
+) -> Result<Vec<u8>> {
+    text_signer(key, format)?.sign(reader)
 }
 
 pub fn process_text_verify(
@@ -148,17 +283,26 @@ pub fn process_text_verify(
     sig: &[u8],
     format: TextSignFormat,
 ) -> Result<bool> {
-    let verifier: Box<dyn TextVerifier> = match format {
-        TextSignFormat::Blake3 => Box::new(Blake3::try_new(key)?),
-        TextSignFormat::Ed25519 => Box::new(Ed25519Verifier::try_new(key)?),
-    };
-    verifier.verify(reader, sig)
+    text_verifier(key, format)?.verify(reader, sig)
 }
 
-pub fn process_text_key_generate(format: TextSignFormat) -> Result<HashMap<&'static str, Vec<u8>>> {
+pub fn process_text_key_generate(
+    format: TextSignFormat,
+    key_format: KeyFormat,
+    passphrase: Option<&str>,
+) -> Result<HashMap<&'static str, Vec<u8>>> {
     match format {
-        TextSignFormat::Blake3 => Blake3::generate(),
-        TextSignFormat::Ed25519 => Ed25519Signer::generate(),
+        TextSignFormat::Blake3 if key_format != KeyFormat::Raw => {
+            Err(ProcessError::UnsupportedFormat(
+                "blake3 keys can only be generated in raw format".into(),
+            ))
+        }
+        TextSignFormat::Blake3 => Blake3::generate(passphrase),
+        // ph 和纯 Ed25519 使用同样的 key pair
+        TextSignFormat::Ed25519 | TextSignFormat::Ed25519ph => {
+            Ed25519Signer::generate(key_format, passphrase)
+        }
+        _ => generate_keys(format, key_format, passphrase),
     }
 }
 
@@ -206,4 +350,46 @@ mod tests {
         assert!(ret);
         Ok(())
     }
+
+    #[test]
+    fn test_ed25519ph_sign_verify() -> Result<()> {
+        let map = process_text_key_generate(TextSignFormat::Ed25519ph, KeyFormat::Raw, None)?;
+        let (sk, pk) = (&map["ed25519.sk"], &map["ed25519.pk"]);
+        // 比 io::copy 的缓冲区大, 确保多次 update 的结果一致
+        let data = vec![42u8; 64 * 1024 + 7];
+        let format = TextSignFormat::Ed25519ph;
+
+        let sig = process_text_sign(&mut data.as_slice(), sk, format)?;
+        assert!(process_text_verify(&mut data.as_slice(), pk, &sig, format)?);
+        // 不能和纯 Ed25519 签名混用
+        let verified =
+            process_text_verify(&mut data.as_slice(), pk, &sig, TextSignFormat::Ed25519)?;
+        assert!(!verified);
+        Ok(())
+    }
+
+    #[test]
+    fn test_short_key_and_signature() -> Result<()> {
+        let ret = process_text_sign(&mut &b"hello"[..], b"short", TextSignFormat::Blake3);
+        assert!(matches!(
+            ret,
+            Err(ProcessError::InvalidKeyLength {
+                expected: 32,
+                actual: 5
+            })
+        ));
+        assert!(process_text_sign(&mut &b"hello"[..], b"short", TextSignFormat::Ed25519).is_err());
+
+        for format in [TextSignFormat::Blake3, TextSignFormat::Ed25519] {
+            let map = process_text_key_generate(format, KeyFormat::Raw, None)?;
+            let key = if format == TextSignFormat::Blake3 {
+                &map["blake3.txt"]
+            } else {
+                &map["ed25519.pk"]
+            };
+            let ret = process_text_verify(&mut &b"hello"[..], key, b"sig", format);
+            assert!(matches!(ret, Err(ProcessError::BadSignature(_))));
+        }
+        Ok(())
+    }
 }
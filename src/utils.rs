@@ -1,5 +1,11 @@
 use anyhow::Result;
-use std::{fs::File, io::Read};
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufWriter, Read, Write},
+    path::Path,
+};
+
+const PASSPHRASE_ENV: &str = "RCLI_PASSPHRASE";
 
 // give a input(-[default] or Path) return a reader, can read_to_end
 
@@ -20,3 +26,56 @@ pub fn get_content(input: &str) -> Result<Vec<u8>> {
     reader.read_to_end(&mut buf)?;
     Ok(buf)
 }
+
+/// write key material, the file is only readable by its owner on unix
+pub fn write_key_file(path: impl AsRef<Path>, content: &[u8]) -> Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(content)?;
+    Ok(())
+}
+
+/// write `path` through a temp file which is renamed once `f` succeeds,
+/// a failed decryption never leaves partial plaintext behind
+pub fn write_file_atomic(
+    path: impl AsRef<Path>,
+    f: impl FnOnce(&mut dyn Write) -> Result<()>,
+) -> Result<()> {
+    let path = path.as_ref();
+    let tmp = path.with_extension("rcli-tmp");
+    let ret = File::create(&tmp)
+        .map_err(anyhow::Error::from)
+        .and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            f(&mut writer)?;
+            Ok(writer.flush()?)
+        });
+    if let Err(e) = ret {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e);
+    }
+    std::fs::rename(tmp, path)?;
+    Ok(())
+}
+
+/// passphrase for private keys: read from `file`, `RCLI_PASSPHRASE`, or prompted on the terminal
+pub fn get_passphrase(file: Option<&str>, confirm: bool) -> Result<String> {
+    let passphrase = if let Some(file) = file {
+        let content = String::from_utf8(get_content(file)?)?;
+        content.trim_end_matches(['\r', '\n']).to_string()
+    } else if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+        passphrase
+    } else {
+        let passphrase = rpassword::prompt_password("Passphrase: ")?;
+        if confirm && passphrase != rpassword::prompt_password("Confirm passphrase: ")? {
+            anyhow::bail!("Passphrases do not match");
+        }
+        passphrase
+    };
+    if passphrase.is_empty() {
+        anyhow::bail!("Passphrase must not be empty");
+    }
+    Ok(passphrase)
+}
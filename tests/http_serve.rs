@@ -0,0 +1,1001 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::Result;
+use rcli::{
+    AccessLogFormat, HttpAuth, HttpCors, HttpJwtAuth, HttpMockConfig, HttpPassword, HttpProxy,
+    HttpServeConfig, HttpUpload, HttpUser, OverwritePolicy, ServeRules,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+const SECRET: &str = "rcli-top-secret";
+
+// 目录结构:
+// <base>/secret.txt            根目录之外, 不能被读到
+// <base>/root/public.txt
+// <base>/root/sub/nested.txt
+// <base>/root/inside -> public.txt
+// <base>/root/outside -> ../secret.txt
+fn fixture(name: &str) -> Result<PathBuf> {
+    let base = std::env::temp_dir().join(format!("rcli-http-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&base);
+    let root = base.join("root");
+    fs::create_dir_all(root.join("sub"))?;
+    fs::write(base.join("secret.txt"), SECRET)?;
+    fs::write(root.join("public.txt"), "public")?;
+    fs::write(root.join("sub/nested.txt"), "nested")?;
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink("public.txt", root.join("inside"))?;
+        std::os::unix::fs::symlink("../secret.txt", root.join("outside"))?;
+    }
+    Ok(root)
+}
+
+async fn start(root: PathBuf, follow_symlinks: bool) -> Result<std::net::SocketAddr> {
+    start_with(HttpServeConfig {
+        path: root,
+        follow_symlinks,
+        ..Default::default()
+    })
+    .await
+}
+
+async fn start_with(config: HttpServeConfig) -> Result<std::net::SocketAddr> {
+    let router = rcli::http_serve_router(&config)?;
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    Ok(addr)
+}
+
+// 直接写原始请求, http 客户端会把 `..` 规范化掉
+async fn request(
+    addr: std::net::SocketAddr,
+    path: &str,
+    headers: &[(&str, &str)],
+) -> Result<(u16, String, Vec<u8>)> {
+    send(addr, "GET", path, headers, &[]).await
+}
+
+async fn send(
+    addr: std::net::SocketAddr,
+    method: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> Result<(u16, String, Vec<u8>)> {
+    let mut stream = TcpStream::connect(addr).await?;
+    let mut request = format!(
+        "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n",
+        method, path
+    );
+    for (name, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    if !body.is_empty() {
+        request.push_str(&format!("Content-Length: {}\r\n", body.len()));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+    stream.write_all(body).await?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+
+    let split = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .map_or(response.len(), |p| p + 4);
+    let body = response.split_off(split);
+    let head = String::from_utf8(response)?;
+    let status = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(0);
+    Ok((status, head, body))
+}
+
+async fn get(addr: std::net::SocketAddr, path: &str) -> Result<(u16, String)> {
+    let (status, _, body) = request(addr, path, &[]).await?;
+    Ok((status, String::from_utf8_lossy(&body).into_owned()))
+}
+
+fn header<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+    head.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.eq_ignore_ascii_case(name).then(|| value.trim())
+    })
+}
+
+#[tokio::test]
+async fn test_serves_files_inside_root() -> Result<()> {
+    let addr = start(fixture("inside")?, false).await?;
+    let (status, body) = get(addr, "/public.txt").await?;
+    assert_eq!(status, 200);
+    assert!(body.ends_with("public"));
+    let (status, body) = get(addr, "/sub/nested.txt").await?;
+    assert_eq!(status, 200);
+    assert!(body.ends_with("nested"));
+    assert_eq!(get(addr, "/./sub/./nested.txt").await?.0, 200);
+    assert_eq!(get(addr, "/missing.txt").await?.0, 404);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rejects_traversal_payloads() -> Result<()> {
+    let addr = start(fixture("traversal")?, false).await?;
+    let forbidden = [
+        "/../secret.txt",
+        "/sub/../../secret.txt",
+        "/sub/%2e%2e/%2e%2e/secret.txt",
+        "/%2e%2e/secret.txt",
+        "/%2E%2E/secret.txt",
+        "/..%2fsecret.txt",
+        "/%2e%2e%2fsecret.txt",
+        "/sub%2f..%2f..%2fsecret.txt",
+        "//etc/passwd",
+        "/%2fetc%2fpasswd",
+        "/sub/..",
+        "/tower/../secret.txt",
+        "/tower/%2e%2e/secret.txt",
+        "/tower/sub%2f..%2f..%2fsecret.txt",
+    ];
+    for payload in forbidden {
+        let (status, body) = get(addr, payload).await?;
+        assert_eq!(status, 403, "{}", payload);
+        assert!(!body.contains(SECRET), "{}", payload);
+    }
+
+    // 这些不会越界, 但也不能读到根目录之外的内容
+    for payload in ["/..%5csecret.txt", "/%00/secret.txt", "/..;/secret.txt"] {
+        let (status, body) = get(addr, payload).await?;
+        assert_ne!(status, 200, "{}", payload);
+        assert!(!body.contains(SECRET), "{}", payload);
+    }
+
+    // 404 页面不回显请求的路径
+    let (status, body) = get(addr, "/%3Cimg%20src=x%20onerror=alert(1)%3E").await?;
+    assert_eq!(status, 404);
+    assert!(!body.contains("<img"));
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_symlink_policy() -> Result<()> {
+    let root = fixture("symlink")?;
+
+    let addr = start(root.clone(), false).await?;
+    assert_eq!(get(addr, "/inside").await?.0, 403);
+    assert_eq!(get(addr, "/outside").await?.0, 403);
+    assert_eq!(get(addr, "/tower/inside").await?.0, 403);
+    assert_eq!(get(addr, "/tower/outside").await?.0, 403);
+
+    // 允许符号链接后, 仍然不能指向根目录之外
+    let addr = start(root, true).await?;
+    let (status, body) = get(addr, "/inside").await?;
+    assert_eq!(status, 200);
+    assert!(body.ends_with("public"));
+    let (status, body) = get(addr, "/outside").await?;
+    assert_eq!(status, 403);
+    assert!(!body.contains(SECRET));
+    let (status, body) = get(addr, "/tower/inside").await?;
+    assert_eq!((status, body.as_str()), (200, "public"));
+    let (status, body) = get(addr, "/tower/outside").await?;
+    assert_eq!(status, 403);
+    assert!(!body.contains(SECRET));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_binary_file_with_mime_type() -> Result<()> {
+    let root = fixture("binary")?;
+    // 不是合法的 utf8
+    let data = (0..=255u8).cycle().take(4096).collect::<Vec<_>>();
+    fs::write(root.join("image.png"), &data)?;
+    let addr = start(root, false).await?;
+
+    let (status, head, body) = request(addr, "/image.png", &[]).await?;
+    assert_eq!(status, 200);
+    assert_eq!(body, data);
+    assert_eq!(header(&head, "content-type"), Some("image/png"));
+    assert_eq!(header(&head, "content-length"), Some("4096"));
+    assert_eq!(header(&head, "accept-ranges"), Some("bytes"));
+
+    let (_, head, _) = request(addr, "/public.txt", &[]).await?;
+    assert_eq!(header(&head, "content-type"), Some("text/plain"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_range_requests() -> Result<()> {
+    let root = fixture("range")?;
+    let data = (0..=255u8).cycle().take(1000).collect::<Vec<_>>();
+    fs::write(root.join("video.mp4"), &data)?;
+    let addr = start(root, false).await?;
+
+    let (status, head, body) = request(addr, "/video.mp4", &[("Range", "bytes=100-199")]).await?;
+    assert_eq!(status, 206);
+    assert_eq!(body, &data[100..200]);
+    assert_eq!(header(&head, "content-range"), Some("bytes 100-199/1000"));
+    assert_eq!(header(&head, "content-length"), Some("100"));
+
+    let (status, _, body) = request(addr, "/video.mp4", &[("Range", "bytes=-10")]).await?;
+    assert_eq!(status, 206);
+    assert_eq!(body, &data[990..]);
+
+    let (status, head, _) = request(addr, "/video.mp4", &[("Range", "bytes=5000-")]).await?;
+    assert_eq!(status, 416);
+    assert_eq!(header(&head, "content-range"), Some("bytes */1000"));
+
+    // If-Range 对不上时返回整个文件
+    let headers = [("Range", "bytes=0-9"), ("If-Range", "\"stale\"")];
+    let (status, _, body) = request(addr, "/video.mp4", &headers).await?;
+    assert_eq!(status, 200);
+    assert_eq!(body, data);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_conditional_requests() -> Result<()> {
+    let addr = start(fixture("conditional")?, false).await?;
+    let (status, head, _) = request(addr, "/public.txt", &[]).await?;
+    assert_eq!(status, 200);
+    let etag = header(&head, "etag").unwrap().to_string();
+    let last_modified = header(&head, "last-modified").unwrap().to_string();
+
+    let (status, _, body) = request(addr, "/public.txt", &[("If-None-Match", &etag)]).await?;
+    assert_eq!(status, 304);
+    assert!(body.is_empty());
+    let (status, _, _) = request(
+        addr,
+        "/public.txt",
+        &[("If-Modified-Since", &last_modified)],
+    )
+    .await?;
+    assert_eq!(status, 304);
+
+    let (status, _, _) = request(addr, "/public.txt", &[("If-None-Match", "\"other\"")]).await?;
+    assert_eq!(status, 200);
+    let old = "Thu, 01 Jan 1970 00:00:00 GMT";
+    let (status, _, _) = request(addr, "/public.txt", &[("If-Modified-Since", old)]).await?;
+    assert_eq!(status, 200);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_directory_listing() -> Result<()> {
+    let root = fixture("listing")?;
+    fs::write(root.join("sub/big file.bin"), vec![0u8; 2048])?;
+    let addr = start(root, false).await?;
+
+    let (status, body) = get(addr, "/").await?;
+    assert_eq!(status, 200);
+    assert!(body.contains("public.txt"));
+    // 不允许的符号链接不出现在列表里
+    assert!(!body.contains("outside"));
+
+    // 不带结尾的 `/` 时链接也要指向子目录里的文件
+    let (status, body) = get(addr, "/sub").await?;
+    assert_eq!(status, 200);
+    assert!(body.contains("href=\"&#x2F;sub&#x2F;big%20file.bin\""));
+    assert!(body.contains("2.0 KiB"));
+    // 上一级目录
+    assert!(body.contains("href=\"&#x2F;\""));
+
+    let (status, head, body) = request(
+        addr,
+        "/sub/?sort=size&order=desc",
+        &[("Accept", "application/json")],
+    )
+    .await?;
+    assert_eq!(status, 200);
+    assert_eq!(header(&head, "content-type"), Some("application/json"));
+    let listing: serde_json::Value = serde_json::from_slice(&body)?;
+    assert_eq!(listing["path"], "/sub/");
+    let entries = listing["entries"].as_array().unwrap();
+    assert_eq!(entries[0]["name"], "big file.bin");
+    assert_eq!(entries[0]["href"], "/sub/big%20file.bin");
+    assert_eq!(entries[0]["size"], 2048);
+    assert_eq!(entries[1]["name"], "nested.txt");
+
+    assert_eq!(get(addr, "/?sort=bogus").await?.0, 400);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_listing_template_override() -> Result<()> {
+    let root = fixture("template")?;
+    let template = root.parent().unwrap().join("listing.html");
+    fs::write(
+        &template,
+        "<h1>ACME {{ path }}</h1>{% for e in entries %}[{{ e.name }}]{% endfor %}",
+    )?;
+    let addr = start_with(HttpServeConfig {
+        path: root.clone(),
+        listing_template: Some(template.clone()),
+        ..Default::default()
+    })
+    .await?;
+
+    let (status, body) = get(addr, "/").await?;
+    assert_eq!(status, 200);
+    assert!(body.contains("<h1>ACME &#x2F;</h1>[sub][public.txt]"));
+
+    // 模板有语法错误时启动失败
+    fs::write(&template, "{% for e in entries %}")?;
+    let config = HttpServeConfig {
+        path: root,
+        listing_template: Some(template),
+        ..Default::default()
+    };
+    assert!(rcli::http_serve_router(&config).is_err());
+    Ok(())
+}
+
+fn upload_config(root: PathBuf, overwrite: OverwritePolicy) -> HttpServeConfig {
+    HttpServeConfig {
+        path: root,
+        upload: Some(HttpUpload {
+            max_size: 1024,
+            overwrite,
+        }),
+        ..Default::default()
+    }
+}
+
+fn multipart(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut body = Vec::new();
+    for (name, content) in files {
+        body.extend_from_slice(
+            format!(
+                "--BOUNDARY\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\n\r\n",
+                name
+            )
+            .as_bytes(),
+        );
+        body.extend_from_slice(content);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(b"--BOUNDARY--\r\n");
+    body
+}
+
+const MULTIPART: (&str, &str) = ("Content-Type", "multipart/form-data; boundary=BOUNDARY");
+
+#[tokio::test]
+async fn test_put_upload() -> Result<()> {
+    let root = fixture("put")?;
+    // 默认不允许上传
+    let addr = start(root.clone(), false).await?;
+    assert_eq!(send(addr, "PUT", "/new.txt", &[], b"data").await?.0, 405);
+    assert!(!root.join("new.txt").exists());
+
+    let addr = start_with(upload_config(root.clone(), OverwritePolicy::Deny)).await?;
+    assert_eq!(
+        send(addr, "PUT", "/sub/new.txt", &[], b"data").await?.0,
+        201
+    );
+    assert_eq!(fs::read(root.join("sub/new.txt"))?, b"data");
+    assert_eq!(
+        send(addr, "PUT", "/sub/new.txt", &[], b"other").await?.0,
+        409
+    );
+    assert_eq!(fs::read(root.join("sub/new.txt"))?, b"data");
+
+    assert_eq!(send(addr, "PUT", "/../evil.txt", &[], b"x").await?.0, 403);
+    assert_eq!(
+        send(addr, "PUT", "/sub/../../evil.txt", &[], b"x").await?.0,
+        403
+    );
+    assert_eq!(send(addr, "PUT", "/missing/a.txt", &[], b"x").await?.0, 404);
+    assert_eq!(send(addr, "PUT", "/sub", &[], b"x").await?.0, 409);
+    assert_eq!(
+        send(addr, "PUT", "/big.bin", &[], &[0u8; 2048]).await?.0,
+        413
+    );
+    assert!(!root.join("big.bin").exists());
+    assert!(!root.parent().unwrap().join("evil.txt").exists());
+
+    let addr = start_with(upload_config(root.clone(), OverwritePolicy::Replace)).await?;
+    assert_eq!(
+        send(addr, "PUT", "/sub/new.txt", &[], b"other").await?.0,
+        204
+    );
+    assert_eq!(fs::read(root.join("sub/new.txt"))?, b"other");
+
+    // 不留下临时文件
+    let leftovers = fs::read_dir(root.join("sub"))?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_name().to_string_lossy().contains("rcli-upload"))
+        .count();
+    assert_eq!(leftovers, 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_multipart_upload() -> Result<()> {
+    let root = fixture("multipart")?;
+    let addr = start_with(upload_config(root.clone(), OverwritePolicy::Rename)).await?;
+
+    // 列表页面带上传表单
+    let (_, body) = get(addr, "/sub/").await?;
+    assert!(body.contains("enctype=\"multipart/form-data\""));
+
+    let body = multipart(&[("a.txt", b"first"), ("C:\\fakepath\\b.bin", &[0, 159, 255])]);
+    let (status, head, _) = send(addr, "POST", "/sub/", &[MULTIPART], &body).await?;
+    assert_eq!(status, 303);
+    assert_eq!(header(&head, "location"), Some("/sub/"));
+    assert_eq!(fs::read(root.join("sub/a.txt"))?, b"first");
+    assert_eq!(fs::read(root.join("sub/b.bin"))?, [0, 159, 255]);
+
+    // 同名文件改名保存
+    let body = multipart(&[("a.txt", b"second")]);
+    let accept = ("Accept", "application/json");
+    let (status, _, response) = send(addr, "POST", "/sub", &[MULTIPART, accept], &body).await?;
+    assert_eq!(status, 200);
+    let response: serde_json::Value = serde_json::from_slice(&response)?;
+    assert_eq!(response["uploaded"][0], "a (1).txt");
+    assert_eq!(fs::read(root.join("sub/a (1).txt"))?, b"second");
+    assert_eq!(fs::read(root.join("sub/a.txt"))?, b"first");
+
+    let body = multipart(&[("root.txt", b"root")]);
+    assert_eq!(send(addr, "POST", "/", &[MULTIPART], &body).await?.0, 303);
+    assert_eq!(fs::read(root.join("root.txt"))?, b"root");
+
+    let body = multipart(&[("big.bin", &[1u8; 2048])]);
+    assert_eq!(send(addr, "POST", "/", &[MULTIPART], &body).await?.0, 413);
+    assert!(!root.join("big.bin").exists());
+
+    // 其他网站的页面不能提交上传表单, 包括同一主机的其他端口
+    let body = multipart(&[("evil.html", b"<script></script>")]);
+    for cross_site in [
+        ("Origin", "https://evil.example"),
+        ("Origin", "http://localhost:3000"),
+        ("Origin", "null"),
+        ("Sec-Fetch-Site", "cross-site"),
+        ("Sec-Fetch-Site", "same-site"),
+    ] {
+        let (status, _, _) = send(addr, "POST", "/", &[MULTIPART, cross_site], &body).await?;
+        assert_eq!(status, 403, "{:?}", cross_site);
+    }
+    assert!(!root.join("evil.html").exists());
+    let same_origin = [
+        MULTIPART,
+        ("Origin", "http://localhost"),
+        ("Sec-Fetch-Site", "same-origin"),
+    ];
+    assert_eq!(send(addr, "POST", "/", &same_origin, &body).await?.0, 303);
+    assert!(root.join("evil.html").exists());
+    Ok(())
+}
+
+fn basic(user: &str, password: &str) -> String {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    format!(
+        "Basic {}",
+        STANDARD.encode(format!("{}:{}", user, password))
+    )
+}
+
+#[tokio::test]
+async fn test_basic_auth() -> Result<()> {
+    let root = fixture("basic-auth")?;
+    let htpasswd = root.parent().unwrap().join("htpasswd");
+    let hash = bcrypt::hash("hunter2", 4)?;
+    fs::write(&htpasswd, format!("# team\nbob:{}\n", hash))?;
+
+    let mut users = vec![HttpUser {
+        name: "alice".to_string(),
+        password: HttpPassword::Plain("s3cret".to_string()),
+    }];
+    users.extend(rcli::load_htpasswd(&htpasswd)?);
+    let addr = start_with(HttpServeConfig {
+        path: root,
+        auth: Some(HttpAuth { users, jwt: None }),
+        ..Default::default()
+    })
+    .await?;
+
+    let (status, head, _) = request(addr, "/public.txt", &[]).await?;
+    assert_eq!(status, 401);
+    assert!(header(&head, "www-authenticate")
+        .unwrap()
+        .starts_with("Basic"));
+    // 目录和 /tower 也一样要认证
+    assert_eq!(request(addr, "/", &[]).await?.0, 401);
+    assert_eq!(request(addr, "/tower/public.txt", &[]).await?.0, 401);
+
+    for (user, password, expected) in [
+        ("alice", "s3cret", 200),
+        ("alice", "s3cret", 200),
+        ("bob", "hunter2", 200),
+        ("alice", "wrong", 401),
+        ("bob", "s3cret", 401),
+        ("mallory", "s3cret", 401),
+    ] {
+        let authorization = basic(user, password);
+        let (status, _, _) =
+            request(addr, "/public.txt", &[("Authorization", &authorization)]).await?;
+        assert_eq!(status, expected, "{}:{}", user, password);
+    }
+    let (status, _, _) = request(addr, "/public.txt", &[("Authorization", "Basic !!!")]).await?;
+    assert_eq!(status, 401);
+
+    fs::write(&htpasswd, "carol:$apr1$abc$def\n")?;
+    assert!(rcli::load_htpasswd(&htpasswd).is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bearer_auth() -> Result<()> {
+    let key = b"rcli-http-key";
+    let addr = start_with(HttpServeConfig {
+        path: fixture("bearer-auth")?,
+        auth: Some(HttpAuth {
+            users: vec![],
+            jwt: Some(HttpJwtAuth {
+                key: key.to_vec(),
+                aud: Some("files".to_string()),
+                sub: Some("ci".to_string()),
+            }),
+        }),
+        ..Default::default()
+    })
+    .await?;
+
+    let (status, head, _) = request(addr, "/public.txt", &[]).await?;
+    assert_eq!(status, 401);
+    assert_eq!(
+        header(&head, "www-authenticate"),
+        Some("Bearer realm=\"rcli\"")
+    );
+
+    let cases = [
+        (rcli::process_jwt_sign(key, "ci", "files", 60)?, 200),
+        (rcli::process_jwt_sign(key, "ci", "other", 60)?, 401),
+        (rcli::process_jwt_sign(key, "someone", "files", 60)?, 401),
+        (
+            rcli::process_jwt_sign(b"wrong-key", "ci", "files", 60)?,
+            401,
+        ),
+    ];
+    for (token, expected) in cases {
+        let authorization = format!("Bearer {}", token);
+        let (status, _, _) =
+            request(addr, "/public.txt", &[("Authorization", &authorization)]).await?;
+        assert_eq!(status, expected);
+    }
+    // 没有配置 Basic 用户
+    let authorization = basic("ci", "files");
+    let (status, _, _) = request(addr, "/public.txt", &[("Authorization", &authorization)]).await?;
+    assert_eq!(status, 401);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_compression() -> Result<()> {
+    let root = fixture("compression")?;
+    let text = "rcli compresses text responses\n".repeat(200);
+    fs::write(root.join("big.txt"), &text)?;
+    let addr = start(root, false).await?;
+
+    for encoding in ["gzip", "br", "zstd"] {
+        let (status, head, body) =
+            request(addr, "/big.txt", &[("Accept-Encoding", encoding)]).await?;
+        assert_eq!(status, 200);
+        assert_eq!(header(&head, "content-encoding"), Some(encoding));
+        assert!(body.len() < text.len() / 4, "{}", encoding);
+    }
+
+    let (_, head, body) = request(addr, "/big.txt", &[]).await?;
+    assert_eq!(header(&head, "content-encoding"), None);
+    assert_eq!(body, text.as_bytes());
+
+    // Range 响应保持原样
+    let headers = [("Accept-Encoding", "gzip"), ("Range", "bytes=0-99")];
+    let (status, head, body) = request(addr, "/big.txt", &headers).await?;
+    assert_eq!(status, 206);
+    assert_eq!(header(&head, "content-encoding"), None);
+    assert_eq!(body, &text.as_bytes()[..100]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cors() -> Result<()> {
+    let cors = HttpCors {
+        origins: vec!["https://app.example".to_string()],
+        allow_credentials: true,
+        max_age: None,
+    };
+    let addr = start_with(HttpServeConfig {
+        path: fixture("cors")?,
+        cors: Some(cors.clone()),
+        auth: Some(HttpAuth {
+            users: vec![HttpUser {
+                name: "alice".to_string(),
+                password: HttpPassword::Plain("s3cret".to_string()),
+            }],
+            jwt: None,
+        }),
+        ..Default::default()
+    })
+    .await?;
+
+    // 预检请求不需要认证
+    let preflight = [
+        ("Origin", "https://app.example"),
+        ("Access-Control-Request-Method", "GET"),
+        ("Access-Control-Request-Headers", "authorization"),
+    ];
+    let (status, head, _) = send(addr, "OPTIONS", "/public.txt", &preflight, &[]).await?;
+    assert_eq!(status, 200);
+    assert_eq!(
+        header(&head, "access-control-allow-origin"),
+        Some("https://app.example")
+    );
+    assert_eq!(
+        header(&head, "access-control-allow-credentials"),
+        Some("true")
+    );
+
+    let authorization = basic("alice", "s3cret");
+    let headers = [
+        ("Origin", "https://app.example"),
+        ("Authorization", authorization.as_str()),
+    ];
+    let (status, head, _) = request(addr, "/public.txt", &headers).await?;
+    assert_eq!(status, 200);
+    assert!(header(&head, "access-control-expose-headers")
+        .unwrap()
+        .contains("content-range"));
+
+    let (_, head, _) = request(addr, "/public.txt", &[("Origin", "https://evil.example")]).await?;
+    assert_eq!(header(&head, "access-control-allow-origin"), None);
+
+    // `*` 不能和 credentials 一起用
+    let config = HttpServeConfig {
+        cors: Some(HttpCors {
+            origins: vec!["*".to_string()],
+            ..cors
+        }),
+        ..Default::default()
+    };
+    assert!(rcli::http_serve_router(&config).is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_render() -> Result<()> {
+    let root = fixture("render")?;
+    fs::write(
+        root.join("README.md"),
+        "# Docs\n\n| a | b |\n|---|---|\n| 1 | <2> |\n\n```rust\nfn main() {}\n```\n",
+    )?;
+    fs::write(root.join("data.csv"), "name,score\nalice,<b>9</b>\nbob,7\n")?;
+    fs::write(
+        root.join("main.rs"),
+        "fn main() {\n    println!(\"hi\");\n}\n",
+    )?;
+    fs::write(root.join("app.js"), "console.log(1);\n")?;
+    let addr = start_with(HttpServeConfig {
+        path: root,
+        render: true,
+        ..Default::default()
+    })
+    .await?;
+    let browser = [("Accept", "text/html,application/xhtml+xml,*/*;q=0.8")];
+
+    let (status, head, body) = request(addr, "/README.md", &browser).await?;
+    let body = String::from_utf8(body)?;
+    assert_eq!(status, 200);
+    assert!(header(&head, "content-type")
+        .unwrap()
+        .starts_with("text/html"));
+    assert!(body.contains("<h1>Docs</h1>"));
+    assert!(body.contains("<td>&lt;2&gt;</td>"));
+    assert!(body.contains("<pre style="));
+
+    let (status, _, body) = request(addr, "/data.csv", &browser).await?;
+    let body = String::from_utf8(body)?;
+    assert_eq!(status, 200);
+    assert!(body.contains("<th>score</th>"));
+    // 单元格内容要转义
+    assert!(body.contains("<td>&lt;b&gt;9&lt;&#x2F;b&gt;</td>"));
+
+    let (_, _, body) = request(addr, "/main.rs", &browser).await?;
+    assert!(String::from_utf8(body)?.contains("println"));
+
+    // `?raw`, 以及不是浏览器导航的请求, 都拿到原文
+    let (_, head, body) = request(addr, "/README.md?raw", &browser).await?;
+    assert!(body.starts_with(b"# Docs"));
+    assert_eq!(header(&head, "vary"), Some("accept"));
+    let (_, _, body) = request(addr, "/app.js", &[("Accept", "*/*")]).await?;
+    assert_eq!(body, b"console.log(1);\n");
+    let (_, head, _) = request(addr, "/data.csv", &[]).await?;
+    assert!(header(&head, "content-type")
+        .unwrap()
+        .starts_with("text/csv"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_watch_live_reload() -> Result<()> {
+    let root = fixture("watch")?;
+    fs::write(
+        root.join("index.html"),
+        "<html><body><h1>v1</h1></body></html>",
+    )?;
+    fs::write(root.join("app.css"), "h1 { color: red; }")?;
+    let addr = start_with(HttpServeConfig {
+        path: root.clone(),
+        watch: true,
+        ..Default::default()
+    })
+    .await?;
+
+    let (status, head, body) = request(addr, "/index.html", &[]).await?;
+    let body = String::from_utf8(body)?;
+    assert_eq!(status, 200);
+    assert!(body.contains("/__rcli/livereload"));
+    assert!(body.trim_end().ends_with("</body></html>"));
+    assert_eq!(
+        header(&head, "content-length"),
+        Some(body.len().to_string().as_str())
+    );
+    // 非 HTML 不注入
+    let (_, body) = get(addr, "/app.css").await?;
+    assert_eq!(body, "h1 { color: red; }");
+
+    // 事件流不会结束, 边读边找
+    let mut stream = TcpStream::connect(addr).await?;
+    stream
+        .write_all(b"GET /__rcli/livereload HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await?;
+    let mut received = String::new();
+    read_until(&mut stream, &mut received, "text/event-stream").await?;
+
+    fs::write(root.join("app.css"), "h1 { color: blue; }")?;
+    read_until(&mut stream, &mut received, "event: css").await?;
+    assert!(received.contains(r#"["/app.css"]"#));
+
+    fs::write(
+        root.join("index.html"),
+        "<html><body><h1>v2</h1></body></html>",
+    )?;
+    read_until(&mut stream, &mut received, "event: reload").await?;
+    assert!(received.contains(r#"["/index.html"]"#));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_spa_and_serve_rules() -> Result<()> {
+    let root = fixture("rules")?;
+    fs::write(root.join("index.html"), "<h1>app</h1>")?;
+    fs::write(root.join("404.html"), "<h1>gone</h1>")?;
+    let shared = root.parent().unwrap().join("shared");
+    fs::create_dir_all(&shared)?;
+    fs::write(shared.join("logo.svg"), "<svg/>")?;
+    let config_path = root.parent().unwrap().join("serve.toml");
+    fs::write(
+        &config_path,
+        r#"
+        [[redirect]]
+        from = "/old/**"
+        to = "/sub/{path}"
+        status = 308
+
+        [[rewrite]]
+        from = "/latest"
+        to = "/public.txt"
+
+        [[headers]]
+        path = "/assets/**"
+        set = { "Cache-Control" = "max-age=31536000, immutable" }
+
+        [[not_found]]
+        page = "/404.html"
+
+        [[mount]]
+        prefix = "/assets"
+        dir = "shared"
+        "#,
+    )?;
+    let rules = rcli::load_serve_rules(&config_path)?;
+    let addr = start_with(HttpServeConfig {
+        path: root.clone(),
+        rules: rules.clone(),
+        ..Default::default()
+    })
+    .await?;
+
+    let (status, head, _) = request(addr, "/old/nested.txt?x=1", &[]).await?;
+    assert_eq!(status, 308);
+    assert_eq!(header(&head, "location"), Some("/sub/nested.txt?x=1"));
+    let (status, body) = get(addr, "/latest").await?;
+    assert_eq!((status, body.as_str()), (200, "public"));
+
+    let (status, head, body) = request(addr, "/assets/logo.svg", &[]).await?;
+    assert_eq!((status, body.as_slice()), (200, b"<svg/>".as_slice()));
+    assert_eq!(
+        header(&head, "cache-control"),
+        Some("max-age=31536000, immutable")
+    );
+    // 挂载目录的列表链接带着前缀
+    let (status, body) = get(addr, "/assets/").await?;
+    assert_eq!(status, 200);
+    assert!(body.contains("href=\"&#x2F;assets&#x2F;logo.svg\""));
+
+    let (status, body) = get(addr, "/no/such/page").await?;
+    assert_eq!((status, body.as_str()), (404, "<h1>gone</h1>"));
+    // 不能借 mount 跑出目录
+    assert_eq!(get(addr, "/assets/../secret.txt").await?.0, 403);
+
+    // --spa: 前端路由拿到 index.html, 缺失的资源还是 404
+    let addr = start_with(HttpServeConfig {
+        path: root,
+        spa: true,
+        rules,
+        ..Default::default()
+    })
+    .await?;
+    let (status, body) = get(addr, "/users/42").await?;
+    assert_eq!((status, body.as_str()), (200, "<h1>app</h1>"));
+    let (status, body) = get(addr, "/missing.js").await?;
+    assert_eq!((status, body.as_str()), (404, "<h1>gone</h1>"));
+
+    for prefix in [
+        "assets",
+        "/assets/:name",
+        "/assets*",
+        "/{assets}",
+        "/__rcli",
+    ] {
+        let bad = ServeRules {
+            mounts: vec![rcli::Mount {
+                prefix: prefix.to_string(),
+                dir: PathBuf::from("."),
+            }],
+            ..Default::default()
+        };
+        let config = HttpServeConfig {
+            rules: bad,
+            ..Default::default()
+        };
+        assert!(rcli::http_serve_router(&config).is_err(), "{}", prefix);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_proxy_and_mock() -> Result<()> {
+    let root = fixture("proxy")?;
+    let spec_path = root.parent().unwrap().join("routes.yaml");
+    fs::write(
+        &spec_path,
+        r#"
+        routes:
+          - path: /users/:id
+            json:
+              id: "{{ id }}"
+              sort: "{{ query.sort }}"
+          - method: POST
+            path: /users
+            status: 201
+            headers:
+              X-Mock: "yes"
+            body: "created {{ body.name }}"
+        "#,
+    )?;
+    let mock = rcli::http_mock_router(&HttpMockConfig {
+        spec: rcli::load_mock_spec(&spec_path)?,
+        addr: "127.0.0.1:0".parse()?,
+        shutdown_timeout: std::time::Duration::from_secs(1),
+        cors: None,
+        access_log: AccessLogFormat::Off,
+    })?;
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let mock_addr = listener.local_addr()?;
+    tokio::spawn(async move { axum::serve(listener, mock).await });
+
+    let addr = start_with(HttpServeConfig {
+        path: root,
+        proxies: vec![HttpProxy {
+            mount: "/api".to_string(),
+            upstream: format!("http://{}", mock_addr),
+            strip_prefix: true,
+        }],
+        ..Default::default()
+    })
+    .await?;
+
+    let (status, head, body) = request(addr, "/api/users/42?sort=name", &[]).await?;
+    assert_eq!(status, 200);
+    assert!(head.contains("content-type: application/json"));
+    let json: serde_json::Value = serde_json::from_slice(&body)?;
+    assert_eq!(json, serde_json::json!({ "id": "42", "sort": "name" }));
+
+    let (status, head, body) = send(
+        addr,
+        "POST",
+        "/api/users",
+        &[("Content-Type", "application/json")],
+        br#"{"name":"alice"}"#,
+    )
+    .await?;
+    assert_eq!(
+        (status, body.as_slice()),
+        (201, b"created alice".as_slice())
+    );
+    assert!(head.contains("x-mock: yes"));
+
+    let (status, body) = get(addr, "/public.txt").await?;
+    assert_eq!((status, body.as_str()), (200, "public"));
+    let (status, _, body) = request(addr, "/api/nothing", &[]).await?;
+    assert_eq!(status, 404);
+    assert!(String::from_utf8(body)?.contains("No mock for GET /nothing"));
+
+    // 上游没有启动
+    let dead = TcpListener::bind("127.0.0.1:0").await?;
+    let dead_addr = dead.local_addr()?;
+    drop(dead);
+    let addr = start_with(HttpServeConfig {
+        path: fixture("proxy-dead")?,
+        proxies: vec![HttpProxy {
+            mount: "/".to_string(),
+            upstream: format!("http://{}", dead_addr),
+            strip_prefix: false,
+        }],
+        ..Default::default()
+    })
+    .await?;
+    let (status, _, _) = request(addr, "/public.txt", &[]).await?;
+    assert_eq!(status, 502);
+
+    // 没有 --dir 时只转发, 不提供当前目录
+    let addr = start_with(HttpServeConfig {
+        path: fixture("proxy-only")?,
+        serve_dir: false,
+        proxies: vec![HttpProxy {
+            mount: "/api".to_string(),
+            upstream: format!("http://{}", mock_addr),
+            strip_prefix: true,
+        }],
+        ..Default::default()
+    })
+    .await?;
+    assert_eq!(get(addr, "/api/users/1?sort=id").await?.0, 200);
+    for path in ["/", "/public.txt", "/tower/public.txt"] {
+        assert_eq!(get(addr, path).await?.0, 404, "{}", path);
+    }
+
+    for mount in ["/api/*x", "/api/:id", "/{api}"] {
+        let config = HttpServeConfig {
+            proxies: vec![HttpProxy {
+                mount: mount.to_string(),
+                upstream: format!("http://{}", mock_addr),
+                strip_prefix: false,
+            }],
+            ..Default::default()
+        };
+        assert!(rcli::http_serve_router(&config).is_err(), "{}", mount);
+    }
+    Ok(())
+}
+
+// 读到 `needle` 出现为止, 最多等 5 秒
+async fn read_until(stream: &mut TcpStream, received: &mut String, needle: &str) -> Result<()> {
+    let mut buf = [0u8; 1024];
+    tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        while !received.contains(needle) {
+            let n = stream.read(&mut buf).await?;
+            anyhow::ensure!(n > 0, "event stream closed");
+            received.push_str(&String::from_utf8_lossy(&buf[..n]));
+        }
+        Ok(())
+    })
+    .await?
+}